@@ -25,4 +25,5 @@ fn main() {
     // ensure cargo re-compiles, if the migrations changed
     //      cf. https://docs.rs/sqlx/latest/sqlx/macro.migrate.html#triggering-recompilation-on-migration-changes
     println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_sqlite");
 }
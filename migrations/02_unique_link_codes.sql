@@ -0,0 +1,2 @@
+-- codes are used to look up links, so they must not be ambiguous
+CREATE UNIQUE INDEX IF NOT EXISTS links_code_uidx ON links (code);
@@ -0,0 +1,3 @@
+ALTER TABLE links
+    ADD COLUMN IF NOT EXISTS expires_at timestamptz,
+    ADD COLUMN IF NOT EXISTS max_visits integer;
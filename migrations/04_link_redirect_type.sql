@@ -0,0 +1,3 @@
+-- HTTP status code used for redirecting, NULL means the server default
+ALTER TABLE links
+    ADD COLUMN IF NOT EXISTS redirect_type smallint CHECK (redirect_type IN (301, 302, 307, 308));
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS api_keys (
+    api_key_id serial NOT NULL PRIMARY KEY,
+    name text NOT NULL,
+    -- first characters of the key, to tell keys apart without storing them
+    prefix text NOT NULL,
+    -- sha256 of the whole key
+    key_hash text NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    revoked_at timestamptz
+);
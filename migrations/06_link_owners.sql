@@ -0,0 +1,19 @@
+-- owners (e.g. teams) of links, admins can see and manage all links
+CREATE TABLE IF NOT EXISTS owners (
+    owner_id serial NOT NULL PRIMARY KEY,
+    name text NOT NULL UNIQUE,
+    is_admin boolean NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- everything created before ownership existed, belongs to the admins
+INSERT INTO owners (name, is_admin) VALUES ('admin', true) ON CONFLICT (name) DO NOTHING;
+
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS owner_id integer REFERENCES owners(owner_id);
+UPDATE api_keys SET owner_id = (SELECT owner_id FROM owners WHERE name = 'admin') WHERE owner_id IS NULL;
+ALTER TABLE api_keys ALTER COLUMN owner_id SET NOT NULL;
+
+ALTER TABLE links ADD COLUMN IF NOT EXISTS owner_id integer REFERENCES owners(owner_id);
+UPDATE links SET owner_id = (SELECT owner_id FROM owners WHERE name = 'admin') WHERE owner_id IS NULL;
+ALTER TABLE links ALTER COLUMN owner_id SET NOT NULL;
+CREATE INDEX IF NOT EXISTS links_owner_id_idx ON links (owner_id);
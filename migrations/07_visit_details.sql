@@ -0,0 +1,11 @@
+ALTER TABLE link_visits
+    ADD COLUMN IF NOT EXISTS referrer text,
+    ADD COLUMN IF NOT EXISTS user_agent text,
+    -- possibly truncated or hashed, depending on the server config
+    ADD COLUMN IF NOT EXISTS ip text,
+    ADD COLUMN IF NOT EXISTS accept_language text,
+    ADD COLUMN IF NOT EXISTS country text,
+    -- classification of the user agent at the time of the visit
+    ADD COLUMN IF NOT EXISTS browser text,
+    ADD COLUMN IF NOT EXISTS os text,
+    ADD COLUMN IF NOT EXISTS device text;
@@ -0,0 +1,16 @@
+-- branded short domains, each with codes of its own
+CREATE TABLE IF NOT EXISTS domains (
+    domain_id serial NOT NULL PRIMARY KEY,
+    -- lowercase, without port
+    host text NOT NULL UNIQUE,
+    -- unknown codes of the domain redirect here, instead of answering with 404
+    fallback_url text,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- NULL is the default domain (cf. `--public-base-url`), which is where all existing links live
+ALTER TABLE links ADD COLUMN IF NOT EXISTS domain_id integer REFERENCES domains(domain_id);
+
+-- codes are unique per domain now, the default domain counting as one of its own
+DROP INDEX IF EXISTS links_code_uidx;
+CREATE UNIQUE INDEX IF NOT EXISTS links_domain_code_uidx ON links (coalesce(domain_id, 0), code);
@@ -0,0 +1,3 @@
+ALTER TABLE links
+    -- argon2 hash in PHC string format, visitors have to enter the password before being redirected
+    ADD COLUMN IF NOT EXISTS password_hash text;
@@ -0,0 +1,5 @@
+ALTER TABLE links
+    -- shown on the preview page
+    ADD COLUMN IF NOT EXISTS title text,
+    -- visitors see a preview page with the target, instead of being redirected right away
+    ADD COLUMN IF NOT EXISTS preview boolean NOT NULL DEFAULT false;
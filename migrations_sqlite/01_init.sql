@@ -0,0 +1,52 @@
+-- The schema of all Postgres migrations at once. Timestamps are RFC 3339 text in UTC with a fixed number of
+-- fractional digits (cf. `db::sqlite`), so comparing them as text compares them in time.
+CREATE TABLE IF NOT EXISTS owners (
+    owner_id integer NOT NULL PRIMARY KEY,
+    name text NOT NULL UNIQUE,
+    -- admins can see and manage all links
+    is_admin boolean NOT NULL DEFAULT false,
+    created_at text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS links (
+    -- AUTOINCREMENT keeps the counter in `sqlite_sequence`, where ids can be reserved up front
+    link_id integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+    code text NOT NULL UNIQUE,
+    url text NOT NULL,
+    created_at text NOT NULL,
+    expires_at text,
+    max_visits integer,
+    -- HTTP status code used for redirecting, NULL means the server default
+    redirect_type integer CHECK (redirect_type IN (301, 302, 307, 308)),
+    owner_id integer NOT NULL REFERENCES owners(owner_id)
+);
+CREATE INDEX IF NOT EXISTS links_owner_id_idx ON links (owner_id);
+INSERT INTO sqlite_sequence (name, seq) VALUES ('links', 0);
+
+CREATE TABLE IF NOT EXISTS link_visits (
+    link_id integer NOT NULL REFERENCES links(link_id),
+    ts text NOT NULL,
+    referrer text,
+    user_agent text,
+    -- possibly truncated or hashed, depending on the server config
+    ip text,
+    accept_language text,
+    country text,
+    -- classification of the user agent at the time of the visit
+    browser text,
+    os text,
+    device text
+);
+CREATE INDEX IF NOT EXISTS link_visits_link_id_ts_idx ON link_visits (link_id, ts);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    api_key_id integer NOT NULL PRIMARY KEY,
+    owner_id integer NOT NULL REFERENCES owners(owner_id),
+    name text NOT NULL,
+    -- first characters of the key, to tell keys apart without storing them
+    prefix text NOT NULL,
+    -- sha256 of the whole key
+    key_hash text NOT NULL UNIQUE,
+    created_at text NOT NULL,
+    revoked_at text
+);
@@ -0,0 +1,56 @@
+-- Like the Postgres migration `08_domains.sql`. SQLite cannot drop the UNIQUE constraint of `links.code`,
+-- so the links are copied to a new table - together with their visits, which reference them.
+CREATE TABLE IF NOT EXISTS domains (
+    domain_id integer NOT NULL PRIMARY KEY,
+    -- lowercase, without port
+    host text NOT NULL UNIQUE,
+    -- unknown codes of the domain redirect here, instead of answering with 404
+    fallback_url text,
+    created_at text NOT NULL
+);
+
+CREATE TABLE links_new (
+    link_id integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+    code text NOT NULL,
+    url text NOT NULL,
+    created_at text NOT NULL,
+    expires_at text,
+    max_visits integer,
+    redirect_type integer CHECK (redirect_type IN (301, 302, 307, 308)),
+    owner_id integer NOT NULL REFERENCES owners(owner_id),
+    -- NULL is the default domain (cf. `--public-base-url`)
+    domain_id integer REFERENCES domains(domain_id)
+);
+INSERT INTO links_new (link_id, code, url, created_at, expires_at, max_visits, redirect_type, owner_id)
+    SELECT link_id, code, url, created_at, expires_at, max_visits, redirect_type, owner_id FROM links;
+-- ids might have been reserved beyond the last link
+DELETE FROM sqlite_sequence WHERE name = 'links_new';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'links_new', seq FROM sqlite_sequence WHERE name = 'links';
+
+CREATE TABLE link_visits_new (
+    link_id integer NOT NULL REFERENCES links_new(link_id),
+    ts text NOT NULL,
+    referrer text,
+    user_agent text,
+    -- possibly truncated or hashed, depending on the server config
+    ip text,
+    accept_language text,
+    country text,
+    -- classification of the user agent at the time of the visit
+    browser text,
+    os text,
+    device text
+);
+INSERT INTO link_visits_new (link_id, ts, referrer, user_agent, ip, accept_language, country, browser, os, device)
+    SELECT link_id, ts, referrer, user_agent, ip, accept_language, country, browser, os, device FROM link_visits;
+
+DROP TABLE link_visits;
+DROP TABLE links;
+-- renaming also updates the reference of the visits and the counter in `sqlite_sequence`
+ALTER TABLE links_new RENAME TO links;
+ALTER TABLE link_visits_new RENAME TO link_visits;
+
+CREATE INDEX links_owner_id_idx ON links (owner_id);
+-- codes are unique per domain now, the default domain counting as one of its own
+CREATE UNIQUE INDEX links_domain_code_uidx ON links (coalesce(domain_id, 0), code);
+CREATE INDEX link_visits_link_id_ts_idx ON link_visits (link_id, ts);
@@ -0,0 +1,2 @@
+-- Like the Postgres migration `09_link_passwords.sql`
+ALTER TABLE links ADD COLUMN password_hash text;
@@ -0,0 +1,3 @@
+-- Like the Postgres migration `10_link_preview.sql`
+ALTER TABLE links ADD COLUMN title text;
+ALTER TABLE links ADD COLUMN preview boolean NOT NULL DEFAULT false;
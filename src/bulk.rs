@@ -0,0 +1,231 @@
+use std::{fs::File, io::BufRead, io::BufReader, path::Path};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::{info, instrument, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    context::AppState,
+    db::{
+        domains::Domains,
+        links::{CreateLinkError, Link, NewLink},
+    },
+    validation::{CreateLinkPayload, InvalidField},
+};
+
+/// How often the import reports its progress
+const PROGRESS_EVERY: usize = 500;
+
+/// What happens to the other links, when one of a bulk creation fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    /// Create every valid link, independent of the others
+    #[default]
+    BestEffort,
+    /// Create all links or none of them
+    Transactional,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BulkError {
+    #[error(transparent)]
+    Invalid(#[from] InvalidField),
+    #[error(transparent)]
+    Create(#[from] CreateLinkError),
+    /// Not created, because another link of the transactional bulk failed
+    #[error("Skipped, because another link failed")]
+    Skipped,
+}
+
+///
+/// Creates many links of the owner, in the order given. The result of each link is reported to `progress`
+/// as soon as it is known and returned at the end. Links can be on any of the registered `domains`.
+///
+/// In [`BulkMode::Transactional`] all links are validated up front and then created in a single transaction,
+/// so either all of them are created or none.
+///
+#[instrument(skip(ctx, domains, payloads, progress), fields(links = payloads.len()))]
+pub async fn create_links(
+    ctx: &AppState,
+    owner_id: i32,
+    domains: &Domains,
+    payloads: Vec<CreateLinkPayload>,
+    mode: BulkMode,
+    mut progress: impl FnMut(usize, &Result<Link, BulkError>),
+) -> Vec<Result<Link, BulkError>> {
+    let mut validated: Vec<Result<NewLink, InvalidField>> = Vec::with_capacity(payloads.len());
+    for payload in payloads {
+        validated.push(payload.validate(ctx, owner_id, domains).await);
+    }
+
+    let mut results = Vec::with_capacity(validated.len());
+    if mode == BulkMode::Transactional {
+        results = create_all(ctx, validated).await;
+        for (idx, result) in results.iter().enumerate() {
+            progress(idx, result);
+        }
+        return results;
+    }
+
+    for (idx, new_link) in validated.into_iter().enumerate() {
+        let result = match new_link {
+            Ok(new_link) => create_link(ctx, &new_link).await.map_err(Into::into),
+            Err(err) => Err(err.into()),
+        };
+        progress(idx, &result);
+        results.push(result);
+    }
+    results
+}
+
+/// Creates all links in a single transaction, if all of them are valid
+async fn create_all(ctx: &AppState, validated: Vec<Result<NewLink, InvalidField>>) -> Vec<Result<Link, BulkError>> {
+    if validated.iter().any(Result::is_err) {
+        return validated
+            .into_iter()
+            .map(|new_link| match new_link {
+                Ok(_) => Err(BulkError::Skipped),
+                Err(err) => Err(err.into()),
+            })
+            .collect();
+    }
+    let new_links: Vec<NewLink> = validated.into_iter().flatten().collect();
+
+    match ctx.links.create_all(&ctx.code_generator, &new_links).await {
+        Ok(Ok(links)) => links
+            .into_iter()
+            .zip(&new_links)
+            .map(|(link, new_link)| {
+                created(ctx, &link, new_link);
+                Ok(link)
+            })
+            .collect(),
+        Ok(Err((failed, err))) => {
+            let mut results: Vec<_> = new_links.iter().map(|_| Err(BulkError::Skipped)).collect();
+            results[failed] = Err(err.into());
+            results
+        }
+        Err(err) => {
+            warn!(err = ?err, "Failed to create links of transactional bulk");
+            new_links
+                .iter()
+                .map(|_| Err(CreateLinkError::Other(anyhow::anyhow!("{err:#}")).into()))
+                .collect()
+        }
+    }
+}
+
+/// Creates a single (validated) link, like it is done for each link of a bulk
+pub async fn create_link(ctx: &AppState, new_link: &NewLink) -> Result<Link, CreateLinkError> {
+    let link = ctx.links.create(&ctx.code_generator, new_link).await?;
+    created(ctx, &link, new_link);
+    Ok(link)
+}
+
+fn created(ctx: &AppState, link: &Link, new_link: &NewLink) {
+    // the code might be cached as unknown
+    ctx.link_cache.invalidate(link.domain_id, &link.code);
+
+    metrics::increment_counter!(
+        "links_created",
+        "with_code" => match new_link.code {
+            Some(_) => "true",
+            None => "false",
+        }
+    );
+}
+
+/// Format of files to import links from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    /// Comma separated values with a header row, naming the fields of the API (e.g. `url,code,max_visits`)
+    Csv,
+    /// One JSON object per line, like the body of `POST /api/links`
+    Jsonl,
+}
+
+impl ImportFormat {
+    /// Guesses the format by the file extension
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Imports links from a file for the owner (created if it does not exist yet).
+/// Rows, which cannot be parsed at all, abort the import before any link is created.
+///
+#[instrument(skip(ctx))]
+pub async fn import(
+    ctx: &AppState,
+    path: &Path,
+    format: Option<ImportFormat>,
+    owner: &str,
+    mode: BulkMode,
+) -> anyhow::Result<()> {
+    let format = match format.or_else(|| ImportFormat::from_path(path)) {
+        Some(format) => format,
+        None => bail!(
+            "Cannot tell the format of {} by its extension, pass `--format`",
+            path.display()
+        ),
+    };
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let payloads = match format {
+        ImportFormat::Csv => read_csv(file)?,
+        ImportFormat::Jsonl => read_jsonl(file)?,
+    };
+    let total = payloads.len();
+    info!(total, "Importing links");
+
+    let owner = ctx.api_keys.find_or_create_owner(owner, false).await?;
+    let domains = ctx.known_domains().await?;
+    let results = create_links(ctx, owner.owner_id, &domains, payloads, mode, |idx, result| {
+        if let Err(ref err) = result {
+            if !matches!(err, BulkError::Skipped) {
+                warn!(entry = idx + 1, err = %err, "Failed to import link");
+            }
+        }
+        if (idx + 1) % PROGRESS_EVERY == 0 {
+            info!(done = idx + 1, total, "Import in progress");
+        }
+    })
+    .await;
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    info!(created = total - failed, failed, "Import finished");
+    if failed > 0 {
+        bail!("{failed} of {total} links were not imported");
+    }
+    Ok(())
+}
+
+fn read_csv(file: File) -> anyhow::Result<Vec<CreateLinkPayload>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .into_deserialize()
+        // the error names the line
+        .map(|row| row.context("Invalid CSV row"))
+        .collect()
+}
+
+fn read_jsonl(file: File) -> anyhow::Result<Vec<CreateLinkPayload>> {
+    let mut payloads = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("Failed to read import file")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let payload = serde_json::from_str(&line).with_context(|| format!("Invalid JSON on line {}", idx + 1))?;
+        payloads.push(payload);
+    }
+    Ok(payloads)
+}
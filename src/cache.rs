@@ -0,0 +1,120 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::{
+    cli::Args,
+    db::{domains::Domains, links::Link, store::DomainStore},
+};
+
+/// Links are looked up by their domain (`None` for the default domain) and code
+type LinkKey = (Option<i32>, String);
+
+///
+/// Bounded LRU cache of links by their domain and code, so redirects of popular links do not hit the database.
+/// Unknown codes are cached as well (as `None`), to not query the database over and over for them.
+/// Entries expire after the TTL, which bounds how long changes made by other instances go unnoticed.
+/// A size of `0` (cf. [`Args`]) disables the cache.
+///
+pub struct LinkCache {
+    entries: Option<Mutex<LruCache<LinkKey, CacheEntry>>>,
+    ttl: Duration,
+}
+
+struct CacheEntry {
+    link: Option<Link>,
+    inserted_at: Instant,
+}
+
+impl LinkCache {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            entries: NonZeroUsize::new(args.link_cache_size).map(|size| Mutex::new(LruCache::new(size))),
+            ttl: args.link_cache_ttl.into(),
+        }
+    }
+
+    ///
+    /// Looks up a code. `None` means the code is not cached, `Some(None)` that it is known to not exist.
+    ///
+    pub fn get(&self, domain_id: Option<i32>, code: &str) -> Option<Option<Link>> {
+        let mut entries = self.entries.as_ref()?.lock().expect("link cache lock poisoned");
+
+        let key = (domain_id, code.to_string());
+        let link = match entries.get(&key) {
+            Some(entry) if entry.inserted_at.elapsed() < self.ttl => Some(entry.link.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        };
+
+        match link {
+            Some(_) => metrics::increment_counter!("link_cache_hits"),
+            None => metrics::increment_counter!("link_cache_misses"),
+        }
+        link
+    }
+
+    pub fn insert(&self, domain_id: Option<i32>, code: &str, link: Option<Link>) {
+        if let Some(ref entries) = self.entries {
+            let entry = CacheEntry {
+                link,
+                inserted_at: Instant::now(),
+            };
+            entries
+                .lock()
+                .expect("link cache lock poisoned")
+                .put((domain_id, code.to_string()), entry);
+        }
+    }
+
+    /// Drops a code, after its link was created, changed or deleted
+    pub fn invalidate(&self, domain_id: Option<i32>, code: &str) {
+        if let Some(ref entries) = self.entries {
+            entries
+                .lock()
+                .expect("link cache lock poisoned")
+                .pop(&(domain_id, code.to_string()));
+        }
+    }
+}
+
+///
+/// All registered domains, reloaded once they are older than the TTL of the link cache. There are only a few,
+/// so each redirect can check its `Host` against them without a query, even for hosts which are no domain.
+/// Domains registered by the CLI are noticed after the TTL. A link cache size of `0` disables this one as well.
+///
+pub struct DomainCache {
+    domains: Mutex<Option<(Arc<Domains>, Instant)>>,
+    ttl: Duration,
+}
+
+impl DomainCache {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            domains: Mutex::new(None),
+            ttl: match args.link_cache_size {
+                0 => Duration::ZERO,
+                _ => args.link_cache_ttl.into(),
+            },
+        }
+    }
+
+    pub async fn get(&self, store: &dyn DomainStore) -> anyhow::Result<Arc<Domains>> {
+        if let Some((ref domains, loaded_at)) = *self.domains.lock().expect("domain cache lock poisoned") {
+            if loaded_at.elapsed() < self.ttl {
+                return Ok(domains.clone());
+            }
+        }
+
+        let domains = Arc::new(Domains(store.list_domains().await?));
+        *self.domains.lock().expect("domain cache lock poisoned") = Some((domains.clone(), Instant::now()));
+        Ok(domains)
+    }
+}
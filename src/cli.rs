@@ -1,7 +1,20 @@
-use std::net::SocketAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
 
+use crate::{
+    bulk::{BulkMode, ImportFormat},
+    codes::{CodeStrategy, BASE62_ALPHABET},
+    db::store::Storage,
+    export::{ExportFormat, ExportKind},
+    redirect::RedirectType,
+    visitor::IpMode,
+};
+
 pub const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 pub const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const PKG_NAME: &str = env!("CARGO_PKG_NAME");
@@ -19,9 +32,12 @@ pub struct Args {
     #[clap(long, env = "SENTRY_DSN")]
     pub sentry_dsn: Option<String>,
 
-    /// URL to the PostgreSQL database
+    /// Where to keep links, visits and API keys
+    #[clap(long, env = "STORAGE", value_enum, default_value_t = Storage::Database)]
+    pub storage: Storage,
+    /// URL to the database, either `postgres://…` or `sqlite://…` (e.g. `sqlite://links.db`)
     #[clap(short = 'd', long, env = "DATABASE_URL")]
-    pub database_url: String,
+    pub database_url: Option<String>,
     #[clap(long, env = "MAX_POOL_SIZE", default_value_t = 10)]
     pub max_db_conn_pool_size: u32,
     #[clap(long, env = "CONN_IDLE_TIMEOUT", default_value = "15min")]
@@ -29,6 +45,102 @@ pub struct Args {
     #[clap(long, env = "CONN_LIFETIME")]
     pub conn_lifetime: Option<humantime::Duration>,
 
+    /// URL schemes links are allowed to redirect to
+    #[clap(
+        long,
+        env = "ALLOWED_URL_SCHEMES",
+        value_delimiter = ',',
+        default_value = "http,https"
+    )]
+    pub allowed_url_schemes: Vec<String>,
+
+    /// How short codes for new links are generated
+    #[clap(long, env = "CODE_STRATEGY", value_enum, default_value_t = CodeStrategy::Random)]
+    pub code_strategy: CodeStrategy,
+    /// Length of randomly generated short codes
+    #[clap(long, env = "CODE_LENGTH", default_value_t = 7)]
+    pub code_length: usize,
+    /// Maximum length of user supplied short codes
+    #[clap(long, env = "MAX_CODE_LENGTH", default_value_t = 32)]
+    pub max_code_length: usize,
+    /// Characters short codes may consist of, out of `A-Z`, `a-z`, `0-9`, `_` and `-`
+    #[clap(long, env = "CODE_ALPHABET", default_value = BASE62_ALPHABET)]
+    pub code_alphabet: String,
+    /// Do not use easily confused characters (like `0`/`O` or `1`/`l`/`I`) in generated codes
+    #[clap(long, env = "CODE_EXCLUDE_LOOKALIKES")]
+    pub code_exclude_lookalikes: bool,
+    /// File with words (one per line), which must not appear in any short code
+    #[clap(long, env = "CODE_BLOCKLIST")]
+    pub code_blocklist: Option<PathBuf>,
+
+    /// Redirect status code for links, which do not specify one
+    #[clap(long, env = "DEFAULT_REDIRECT_TYPE", value_enum, default_value_t = RedirectType::TemporaryRedirect)]
+    pub default_redirect_type: RedirectType,
+    /// Base of the short URLs handed out, e.g. `https://sho.rt` - derived from the `Host` header if not set
+    #[clap(long, env = "PUBLIC_BASE_URL")]
+    pub public_base_url: Option<url::Url>,
+    /// Redirect visitors of expired links here, instead of answering with `410 Gone`
+    #[clap(long, env = "EXPIRED_LINK_FALLBACK_URL")]
+    pub expired_link_fallback_url: Option<String>,
+    /// Directory with `preview.html` and/or `password.html`, replacing the built-in pages (cf. `templates/`)
+    #[clap(long, env = "TEMPLATES_DIR")]
+    pub templates_dir: Option<PathBuf>,
+
+    /// Links each API key may create per minute, `0` disables the limit
+    #[clap(long, env = "CREATE_RATE_LIMIT", default_value_t = 60)]
+    pub create_rate_limit: u32,
+    /// Link creations each client IP may attempt per minute, with any (even invalid) API keys, `0` disables the limit
+    #[clap(long, env = "CREATE_IP_RATE_LIMIT", default_value_t = 120)]
+    pub create_ip_rate_limit: u32,
+    /// Redirects each client IP may follow per minute, `0` disables the limit
+    #[clap(long, env = "REDIRECT_RATE_LIMIT", default_value_t = 600)]
+    pub redirect_rate_limit: u32,
+    /// Password attempts per protected link and minute (across all clients), `0` disables the limit
+    #[clap(long, env = "PASSWORD_ATTEMPT_LIMIT", default_value_t = 10)]
+    pub password_attempt_limit: u32,
+    /// Addresses of reverse proxies, whose `X-Forwarded-For` header is trusted to name the client IP
+    #[clap(long, env = "TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// How much of the visitor IP address is recorded with each visit
+    #[clap(long, env = "VISITOR_IP_MODE", value_enum, default_value_t = IpMode::Truncate)]
+    pub visitor_ip_mode: IpMode,
+    /// Secret salt for hashing visitor IP addresses, required for `--visitor-ip-mode hash`
+    #[clap(long, env = "IP_HASH_SALT", hide_env_values = true)]
+    pub ip_hash_salt: Option<String>,
+    /// Header set by a proxy or CDN, which contains the visitor's country code (e.g. `CF-IPCountry`).
+    /// Without it, the country of visits is unknown.
+    #[clap(long, env = "COUNTRY_HEADER")]
+    pub country_header: Option<String>,
+    /// Visits waiting to be written to the database, before further visits are dropped
+    #[clap(long, env = "VISIT_QUEUE_SIZE", default_value_t = 10000)]
+    pub visit_queue_size: usize,
+    /// Maximum number of visits written to the database at once
+    #[clap(long, env = "VISIT_BATCH_SIZE", default_value_t = 500)]
+    pub visit_batch_size: usize,
+    /// How often queued visits are written to the database, even if the batch is not full
+    #[clap(long, env = "VISIT_FLUSH_INTERVAL", default_value = "1s")]
+    pub visit_flush_interval: humantime::Duration,
+
+    /// Number of links kept in memory for fast redirects, `0` disables the cache
+    #[clap(long, env = "LINK_CACHE_SIZE", default_value_t = 10000)]
+    pub link_cache_size: usize,
+    /// How long cached links (and unknown codes) are kept, before looking them up again
+    #[clap(long, env = "LINK_CACHE_TTL", default_value = "60s")]
+    pub link_cache_ttl: humantime::Duration,
+
+    /// Maximum number of links created by a single bulk request, which counts as one creation for the rate limit
+    #[clap(long, env = "MAX_BULK_LINKS", default_value_t = 1000)]
+    pub max_bulk_links: usize,
+
+    /// Serve a Swagger UI page for the API at `/api/docs`
+    #[clap(long, env = "API_DOCS_UI")]
+    pub api_docs_ui: bool,
+
+    /// Maximum number of entries returned by paginated list endpoints
+    #[clap(long, env = "MAX_PAGE_SIZE", default_value_t = 100)]
+    pub max_page_size: i64,
+
     #[clap(subcommand)]
     pub command: CliCommand,
 }
@@ -42,4 +154,86 @@ pub enum CliCommand {
     },
     /// Run the sql migrations
     Migrate,
+    /// Delete links (and their visits), which expired a while ago
+    Cleanup {
+        /// How long to keep expired links around, before deleting them
+        #[clap(long, env = "CLEANUP_GRACE_PERIOD", default_value = "30days")]
+        grace_period: humantime::Duration,
+    },
+    /// Print the OpenAPI document of the HTTP API, e.g. for generating clients
+    Openapi,
+    /// Create links from a CSV or JSON Lines file
+    Import {
+        file: PathBuf,
+        /// Format of the file, guessed by its extension if not given
+        #[clap(long, value_enum)]
+        format: Option<ImportFormat>,
+        /// Name of the owner of the imported links - created if it does not exist yet
+        #[clap(long)]
+        owner: String,
+        /// Whether to import the valid links only, or nothing if any link fails
+        #[clap(long, value_enum, default_value_t = BulkMode::BestEffort)]
+        mode: BulkMode,
+    },
+    /// Export all links or visits as CSV, JSON Lines or Parquet
+    Export {
+        #[clap(long, value_enum, default_value_t = ExportKind::Links)]
+        kind: ExportKind,
+        /// Format of the export, guessed by the extension of the output file if not given (CSV otherwise)
+        #[clap(long, value_enum)]
+        format: Option<ExportFormat>,
+        /// File to write to, stdout if not given
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Only export visits since then, e.g. `2023-07-01T00:00:00Z`
+        #[clap(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only export visits before then
+        #[clap(long)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Manage API keys for the link management endpoints
+    ApiKey {
+        #[clap(subcommand)]
+        command: ApiKeyCommand,
+    },
+    /// Manage custom domains, each with short codes of its own
+    Domain {
+        #[clap(subcommand)]
+        command: DomainCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ApiKeyCommand {
+    /// Create a new API key and print it
+    Create {
+        /// Describes who uses the key
+        #[clap(long)]
+        name: String,
+        /// Name of the owner (e.g. team), the key acts for - created if it does not exist yet
+        #[clap(long)]
+        owner: String,
+        /// Make the owner an admin, who can see and manage the links of all owners
+        #[clap(long)]
+        admin: bool,
+    },
+    /// List all API keys
+    List,
+    /// Revoke an API key, so it can no longer be used
+    Revoke { api_key_id: i32 },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DomainCommand {
+    /// Register a domain (pointed at this server), or update its fallback URL
+    Add {
+        /// Host name like `go.example.com`
+        host: String,
+        /// Redirect unknown codes of the domain here, instead of answering with `404 Not Found`
+        #[clap(long)]
+        fallback_url: Option<String>,
+    },
+    /// List all domains
+    List,
 }
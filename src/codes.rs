@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+
+use crate::cli::Args;
+
+pub const BASE62_ALPHABET: &str = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Characters that are easily mistaken for each other, when reading or typing a code
+const LOOKALIKES: &[char] = &['0', 'O', '1', 'I', 'l'];
+
+/// Codes, which would be shadowed by other routes at the root (`/:code`) or below `/api/links`
+pub const RESERVED_CODES: &[&str] = &["api", "metrics", "robots.txt", "favicon.ico", "bulk", "export"];
+
+/// Appended to a short URL, it shows the preview page of the link instead of redirecting (e.g. `/foo+`)
+pub const PREVIEW_SUFFIX: char = '+';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CodeStrategy {
+    /// Random codes of a fixed length
+    Random,
+    /// The link id encoded with the alphabet (e.g. base62) - short, but guessable
+    Sequential,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodeError {
+    #[error("code must not be empty")]
+    Empty,
+    #[error("code must not be longer than {0} characters")]
+    TooLong(usize),
+    #[error("character '{0}' is not allowed")]
+    InvalidChar(char),
+    #[error("code contains a blocked word")]
+    Blocked,
+    #[error("code is reserved")]
+    Reserved,
+}
+
+///
+/// Generates short codes and validates user supplied ones, according to the rules configured in [`Args`].
+///
+#[derive(Debug, Clone)]
+pub struct CodeGenerator {
+    strategy: CodeStrategy,
+    length: usize,
+    max_length: usize,
+    /// all characters allowed in a code
+    alphabet: Vec<char>,
+    /// characters used for generating codes (the alphabet, possibly without look-alikes)
+    generator_alphabet: Vec<char>,
+    /// lowercase words, which must not be part of any code
+    blocklist: Vec<String>,
+}
+
+impl CodeGenerator {
+    pub fn from_args(args: &Args) -> anyhow::Result<Self> {
+        // dedup the alphabet, while keeping its order
+        let mut seen = BTreeSet::new();
+        let alphabet: Vec<char> = args.code_alphabet.chars().filter(|c| seen.insert(*c)).collect();
+        let generator_alphabet: Vec<char> = alphabet
+            .iter()
+            .copied()
+            .filter(|c| !args.code_exclude_lookalikes || !LOOKALIKES.contains(c))
+            .collect();
+
+        // anything else would have to be escaped in URLs, or is part of their syntax (like `/`, `?` and `#`, but
+        // also the `+` requesting the preview of a link). Multi-byte characters would also throw off nanoid, which
+        // counts the length of codes in bytes.
+        if let Some(c) = alphabet.iter().find(|c| !is_url_safe(**c)) {
+            bail!("Code alphabet must only contain the characters `A-Z`, `a-z`, `0-9`, `_` and `-`, not '{c}'");
+        }
+        // nanoid supports at most 255 characters
+        if !(2..=255).contains(&generator_alphabet.len()) {
+            bail!("Code alphabet must consist of 2 to 255 distinct characters");
+        }
+        if args.code_length == 0 || args.code_length > args.max_code_length {
+            bail!("Code length must be between 1 and the max code length");
+        }
+
+        let blocklist = match args.code_blocklist {
+            Some(ref path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read code blocklist from {}", path.display()))?
+                .lines()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            strategy: args.code_strategy,
+            length: args.code_length,
+            max_length: args.max_code_length,
+            alphabet,
+            generator_alphabet,
+            blocklist,
+        })
+    }
+
+    pub fn strategy(&self) -> CodeStrategy {
+        self.strategy
+    }
+
+    /// Random code of the configured length
+    pub fn random(&self) -> String {
+        nanoid::format(nanoid::rngs::default, &self.generator_alphabet, self.length)
+    }
+
+    /// Encodes the (positive) id with the alphabet as digits
+    pub fn encode_id(&self, id: i32) -> String {
+        let base = self.generator_alphabet.len() as u32;
+        let mut rest = id.unsigned_abs();
+        let mut code = Vec::new();
+        loop {
+            code.push(self.generator_alphabet[(rest % base) as usize]);
+            rest /= base;
+            if rest == 0 {
+                break;
+            }
+        }
+        code.iter().rev().collect()
+    }
+
+    pub fn is_blocked(&self, code: &str) -> bool {
+        let code = code.to_lowercase();
+        self.blocklist.iter().any(|word| code.contains(word.as_str()))
+    }
+
+    /// Whether the code cannot be reached, because a route of the server has the same path
+    pub fn is_reserved(&self, code: &str) -> bool {
+        RESERVED_CODES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(code))
+    }
+
+    ///
+    /// Checks a user supplied code. It must only consist of characters of the alphabet
+    /// (look-alikes are fine here, since the user chose them deliberately).
+    ///
+    pub fn validate(&self, code: &str) -> Result<(), CodeError> {
+        if code.is_empty() {
+            return Err(CodeError::Empty);
+        }
+        if code.chars().count() > self.max_length {
+            return Err(CodeError::TooLong(self.max_length));
+        }
+        if let Some(c) = code.chars().find(|c| !self.alphabet.contains(c)) {
+            return Err(CodeError::InvalidChar(c));
+        }
+        if self.is_blocked(code) {
+            return Err(CodeError::Blocked);
+        }
+        if self.is_reserved(code) {
+            return Err(CodeError::Reserved);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the character can be part of a URL path as is, without being part of its syntax
+fn is_url_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn from_alphabet(alphabet: &str, exclude_lookalikes: bool) -> anyhow::Result<CodeGenerator> {
+        let mut args = vec!["rust-axum-demo", "--code-alphabet", alphabet];
+        if exclude_lookalikes {
+            args.push("--code-exclude-lookalikes");
+        }
+        args.push("serve");
+        CodeGenerator::from_args(&Args::parse_from(args))
+    }
+
+    #[test]
+    fn url_safe_alphabets_are_accepted() {
+        let generator = from_alphabet("ab", false).unwrap();
+        assert!(generator.random().chars().all(|c| c == 'a' || c == 'b'));
+        let generator = from_alphabet(&format!("{BASE62_ALPHABET}_-"), true).unwrap();
+        assert_eq!(generator.random().len(), 7);
+    }
+
+    #[test]
+    fn too_small_alphabets_are_rejected() {
+        assert!(from_alphabet("a", false).is_err());
+        assert!(from_alphabet("aaaa", false).is_err());
+        // too few left without the look-alikes
+        assert!(from_alphabet("01", true).is_err());
+    }
+
+    #[test]
+    fn too_large_alphabets_are_rejected() {
+        let alphabet: String = (1..=256).filter_map(char::from_u32).collect();
+        assert!(from_alphabet(&alphabet, false).is_err());
+    }
+
+    #[test]
+    fn url_syntax_is_rejected() {
+        for c in ['+', '/', '?', '#', '%', '&', '.', ' ', '\t'] {
+            assert!(from_alphabet(&format!("ab{c}"), false).is_err(), "{c:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn non_ascii_characters_are_rejected() {
+        assert!(from_alphabet("abä", false).is_err());
+        assert!(from_alphabet("ab😀", false).is_err());
+    }
+}
@@ -1,16 +1,32 @@
 use std::{ops::Deref, str::FromStr, sync::Arc, time::Duration};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use metrics_exporter_prometheus::PrometheusHandle;
 use sqlx::{
+    migrate::MigrateError,
     postgres::{PgConnectOptions, PgPoolOptions},
-    ConnectOptions, Pool, Postgres,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    ConnectOptions, Pool, Postgres, Sqlite,
 };
 use tracing::instrument;
 
 use crate::{
+    cache::{DomainCache, LinkCache},
     cli::{Args, PKG_NAME},
+    codes::CodeGenerator,
+    db::{
+        domains::Domains,
+        memory::MemoryStore,
+        postgres::PgStore,
+        sqlite::SqliteStore,
+        store::{ApiKeyStore, DomainStore, LinkStore, Storage, VisitStore},
+    },
+    routes::rate_limit::RateLimiters,
     telemetry,
+    templates::Templates,
+    validation::normalize_url,
+    visit_queue::VisitQueue,
+    visitor::IpMode,
 };
 
 ///
@@ -29,6 +45,14 @@ impl AppState {
             inner: Arc::new(AppStateInner::new(args).await?),
         })
     }
+
+    /// Context on top of an existing DB pool, e.g. the one of a test database
+    #[cfg(test)]
+    pub fn with_pool(args: Args, pool: Pool<Postgres>) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(AppStateInner::with_backend(args, Backend::Postgres(pool))?),
+        })
+    }
 }
 
 impl Deref for AppState {
@@ -39,32 +63,162 @@ impl Deref for AppState {
     }
 }
 
+///
+/// The storage backend, as far as it matters besides the stores (e.g. for running migrations)
+///
+pub enum Backend {
+    Postgres(Pool<Postgres>),
+    Sqlite(Pool<Sqlite>),
+    Memory,
+}
+
+impl Backend {
+    /// Applies the migrations of the backend, Postgres and SQLite have their own set each
+    pub async fn migrate(&self) -> Result<(), MigrateError> {
+        match self {
+            Self::Postgres(pool) => sqlx::migrate!().run(pool).await,
+            Self::Sqlite(pool) => sqlx::migrate!("./migrations_sqlite").run(pool).await,
+            Self::Memory => Ok(()),
+        }
+    }
+}
+
 pub struct AppStateInner {
     pub args: Args,
-    pub pool: Pool<Postgres>,
+    pub backend: Backend,
+    pub links: Arc<dyn LinkStore>,
+    pub visits: Arc<dyn VisitStore>,
+    pub api_keys: Arc<dyn ApiKeyStore>,
+    pub domains: Arc<dyn DomainStore>,
     pub prom_handle: PrometheusHandle,
+    pub code_generator: CodeGenerator,
+    pub rate_limiters: RateLimiters,
+    pub visit_queue: VisitQueue,
+    pub link_cache: LinkCache,
+    pub domain_cache: DomainCache,
+    pub templates: Templates,
 }
 
 impl AppStateInner {
     async fn new(args: Args) -> anyhow::Result<Self> {
-        let mut db_options = PgConnectOptions::from_str(&args.database_url)?.application_name(PKG_NAME);
-        db_options.log_statements(tracing::log::LevelFilter::Debug);
-        db_options.log_slow_statements(tracing::log::LevelFilter::Warn, Duration::from_millis(250));
-
-        let db_pool = PgPoolOptions::new()
-            .max_connections(args.max_db_conn_pool_size)
-            .idle_timeout(args.conn_idle_timeout.map(|t| t.into()))
-            .max_lifetime(args.conn_lifetime.map(|t| t.into()))
-            .connect_with(db_options)
-            .await
-            .context("Failed to create DB pool")?;
+        let backend = match args.storage {
+            Storage::Database => connect_database(&args).await?,
+            Storage::Memory => Backend::Memory,
+        };
+        Self::with_backend(args, backend)
+    }
+
+    fn with_backend(mut args: Args, backend: Backend) -> anyhow::Result<Self> {
+        if args.visitor_ip_mode == IpMode::Hash && args.ip_hash_salt.is_none() {
+            // without a salt IPv4 addresses could simply be brute forced from their hashes
+            bail!("Hashing visitor IPs requires an IP hash salt");
+        }
+        if args.visit_queue_size == 0 || args.visit_batch_size == 0 {
+            bail!("Visit queue and batch size must be at least 1");
+        }
+        if args.max_page_size < 1 {
+            // the page size requested is clamped to it
+            bail!("Max page size must be at least 1");
+        }
+        if let Some(ref url) = args.expired_link_fallback_url {
+            // it ends up in the `Location` header of redirects
+            let url = normalize_url(url, &args.allowed_url_schemes).context("Invalid expired link fallback URL")?;
+            args.expired_link_fallback_url = Some(url);
+        }
 
         let prom_handle = telemetry::setup_metrics();
+        let code_generator = CodeGenerator::from_args(&args).context("Invalid short code config")?;
+        let rate_limiters = RateLimiters::from_args(&args);
+        let (links, visits, api_keys, domains) = match backend {
+            Backend::Postgres(ref pool) => split_store(PgStore::new(pool.clone())),
+            Backend::Sqlite(ref pool) => split_store(SqliteStore::new(pool.clone())),
+            Backend::Memory => split_store(MemoryStore::new()),
+        };
+        let visit_queue = VisitQueue::start(visits.clone(), &args);
+        let link_cache = LinkCache::from_args(&args);
+        let domain_cache = DomainCache::from_args(&args);
+        let templates = Templates::from_args(&args)?;
 
         Ok(Self {
             args,
-            pool: db_pool,
+            backend,
+            links,
+            visits,
+            api_keys,
+            domains,
             prom_handle,
+            code_generator,
+            rate_limiters,
+            visit_queue,
+            link_cache,
+            domain_cache,
+            templates,
         })
     }
+
+    /// The registered domains, cf. [`DomainCache`]
+    pub async fn known_domains(&self) -> anyhow::Result<Arc<Domains>> {
+        self.domain_cache.get(self.domains.as_ref()).await
+    }
+}
+
+/// Picks the backend by the scheme of the database URL
+async fn connect_database(args: &Args) -> anyhow::Result<Backend> {
+    let database_url = args
+        .database_url
+        .as_deref()
+        .context("A database URL is required, unless the storage is `memory`")?;
+
+    match database_url.split_once(':').map(|(scheme, _)| scheme) {
+        Some("postgres" | "postgresql") => Ok(Backend::Postgres(connect_postgres(args, database_url).await?)),
+        Some("sqlite") => Ok(Backend::Sqlite(connect_sqlite(args, database_url).await?)),
+        _ => bail!("Unsupported database URL, it has to start with `postgres://` or `sqlite://`"),
+    }
+}
+
+async fn connect_postgres(args: &Args, database_url: &str) -> anyhow::Result<Pool<Postgres>> {
+    let mut db_options = PgConnectOptions::from_str(database_url)?.application_name(PKG_NAME);
+    db_options.log_statements(tracing::log::LevelFilter::Debug);
+    db_options.log_slow_statements(tracing::log::LevelFilter::Warn, Duration::from_millis(250));
+
+    PgPoolOptions::new()
+        .max_connections(args.max_db_conn_pool_size)
+        .idle_timeout(args.conn_idle_timeout.map(|t| t.into()))
+        .max_lifetime(args.conn_lifetime.map(|t| t.into()))
+        .connect_with(db_options)
+        .await
+        .context("Failed to create DB pool")
+}
+
+async fn connect_sqlite(args: &Args, database_url: &str) -> anyhow::Result<Pool<Sqlite>> {
+    let mut db_options = SqliteConnectOptions::from_str(database_url)?
+        .create_if_missing(true)
+        // readers do not block the writer (e.g. the visit queue) and vice versa
+        .journal_mode(SqliteJournalMode::Wal);
+    db_options.log_statements(tracing::log::LevelFilter::Debug);
+    db_options.log_slow_statements(tracing::log::LevelFilter::Warn, Duration::from_millis(250));
+
+    SqlitePoolOptions::new()
+        .max_connections(args.max_db_conn_pool_size)
+        .idle_timeout(args.conn_idle_timeout.map(|t| t.into()))
+        .max_lifetime(args.conn_lifetime.map(|t| t.into()))
+        .connect_with(db_options)
+        .await
+        .context("Failed to create SQLite pool")
+}
+
+type Stores = (
+    Arc<dyn LinkStore>,
+    Arc<dyn VisitStore>,
+    Arc<dyn ApiKeyStore>,
+    Arc<dyn DomainStore>,
+);
+
+/// One store implements all the traits, share it between them
+fn split_store<S>(store: S) -> Stores
+where
+    S: LinkStore + VisitStore + ApiKeyStore + DomainStore + 'static,
+{
+    let store = Arc::new(store);
+    (store.clone(), store.clone(), store.clone(), store)
 }
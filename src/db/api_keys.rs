@@ -0,0 +1,113 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as, FromRow, PgPool};
+use tracing::instrument;
+
+use crate::codes::BASE62_ALPHABET;
+
+/// Prefix of all keys, so they are easy to recognize (e.g. by secret scanners)
+const KEY_PREFIX: &str = "axd_";
+const KEY_LENGTH: usize = 40;
+/// Number of characters of the key stored in plain text, to identify it
+const VISIBLE_PREFIX_LENGTH: usize = KEY_PREFIX.len() + 6;
+
+///
+/// API key for accessing the management endpoints.
+/// Only a hash of the key is stored, so the key itself is only known once at creation.
+///
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ApiKey {
+    pub api_key_id: i32,
+    pub owner_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    #[instrument(skip(pool, key_hash))]
+    pub async fn insert(
+        pool: &PgPool,
+        owner_id: i32,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+    ) -> anyhow::Result<Self> {
+        query_as!(
+            Self,
+            r#"Insert Into api_keys (owner_id, name, prefix, key_hash) Values ($1, $2, $3, $4)
+                Returning api_key_id, owner_id, name, prefix, created_at, revoked_at"#,
+            owner_id,
+            name,
+            prefix,
+            key_hash,
+        )
+        .fetch_one(pool)
+        .await
+        .context("Failed to create API key")
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn list(pool: &PgPool) -> anyhow::Result<Vec<Self>> {
+        query_as!(
+            Self,
+            "Select api_key_id, owner_id, name, prefix, created_at, revoked_at From api_keys Order By api_key_id"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to list API keys")
+    }
+
+    ///
+    /// Looks up an active (not revoked) key by its plain value.
+    ///
+    #[instrument(skip_all)]
+    pub async fn find_active(pool: &PgPool, key: &str) -> anyhow::Result<Option<Self>> {
+        query_as!(
+            Self,
+            r#"Select api_key_id, owner_id, name, prefix, created_at, revoked_at From api_keys
+                Where key_hash = $1 And revoked_at Is Null"#,
+            hash_key(key),
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up API key")
+    }
+
+    ///
+    /// Revokes the key, returns `false` if there was no active key with this id.
+    ///
+    #[instrument(skip(pool))]
+    pub async fn revoke(pool: &PgPool, api_key_id: i32) -> anyhow::Result<bool> {
+        query!(
+            "Update api_keys Set revoked_at = now() Where api_key_id = $1 And revoked_at Is Null",
+            api_key_id
+        )
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to revoke API key {api_key_id}"))
+        .map(|result| result.rows_affected() > 0)
+    }
+}
+
+/// New random key
+pub fn generate_key() -> String {
+    let alphabet: Vec<char> = BASE62_ALPHABET.chars().collect();
+    format!(
+        "{KEY_PREFIX}{}",
+        nanoid::format(nanoid::rngs::default, &alphabet, KEY_LENGTH)
+    )
+}
+
+/// Part of the key stored in plain text, to identify it
+pub fn visible_prefix(key: &str) -> &str {
+    &key[..VISIBLE_PREFIX_LENGTH]
+}
+
+// The keys are long random strings, so a plain (fast) hash is sufficient - there is nothing to brute force.
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
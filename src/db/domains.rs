@@ -0,0 +1,60 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{query_as, FromRow, PgPool};
+use tracing::instrument;
+
+///
+/// Branded short domain with a namespace of codes of its own. Links without domain live on the default one,
+/// which is served under `--public-base-url`.
+///
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Domain {
+    pub domain_id: i32,
+    /// lowercase, without port (cf. [`crate::validation::normalize_host`])
+    pub host: String,
+    /// unknown codes of the domain redirect here, instead of answering with `404 Not Found`
+    pub fallback_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Domain {
+    #[instrument(skip(pool))]
+    pub async fn list(pool: &PgPool) -> anyhow::Result<Vec<Self>> {
+        query_as!(Self, "Select * From domains Order By domain_id")
+            .fetch_all(pool)
+            .await
+            .context("Failed to list domains")
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn upsert(pool: &PgPool, host: &str, fallback_url: Option<&str>) -> anyhow::Result<Self> {
+        query_as!(
+            Self,
+            r#"Insert Into domains (host, fallback_url) Values ($1, $2)
+                On Conflict (host) Do Update Set fallback_url = excluded.fallback_url
+                Returning *"#,
+            host,
+            fallback_url,
+        )
+        .fetch_one(pool)
+        .await
+        .with_context(|| format!("Failed to register domain {host}"))
+    }
+}
+
+/// All registered domains, cf. [`crate::cache::DomainCache`]
+#[derive(Debug, Default)]
+pub struct Domains(pub Vec<Domain>);
+
+impl Domains {
+    pub fn by_host(&self, host: &str) -> Option<&Domain> {
+        self.0.iter().find(|domain| domain.host == host)
+    }
+
+    /// `None` for the default domain
+    pub fn by_id(&self, domain_id: Option<i32>) -> Option<&Domain> {
+        let domain_id = domain_id?;
+        self.0.iter().find(|domain| domain.domain_id == domain_id)
+    }
+}
@@ -1,12 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+
 use anyhow::Context;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Days, TimeZone, Timelike, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
 use sqlx::{query, query_as, FromRow, PgPool};
 use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::{owners::OwnerScope, store::ExportStream};
 
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone, FromRow, Serialize)]
 pub struct LinkVisit {
     pub link_id: i32,
     pub ts: DateTime<Utc>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub accept_language: Option<String>,
+    pub country: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub device: Option<String>,
+}
+
+/// A [`LinkVisit`] together with the code of its link, as exported
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ExportedVisit {
+    pub link_id: i32,
+    pub code: String,
+    pub ts: DateTime<Utc>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub accept_language: Option<String>,
+    pub country: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub device: Option<String>,
+}
+
+/// Width of the time buckets visits are counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl TimeBucket {
+    /// Name as understood by `date_trunc()`
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+
+    /// Start of the bucket containing `ts`, in UTC like `date_trunc()` (so weeks start on Monday)
+    pub fn truncate(self, ts: DateTime<Utc>) -> DateTime<Utc> {
+        let date = ts.date_naive();
+        let start = match self {
+            Self::Hour => date.and_hms_opt(ts.hour(), 0, 0),
+            Self::Day => date.and_hms_opt(0, 0, 0),
+            Self::Week => (date - Days::new(ts.weekday().num_days_from_monday().into())).and_hms_opt(0, 0, 0),
+        };
+        Utc.from_utc_datetime(&start.expect("midnight and full hours are valid times"))
+    }
+
+    pub fn duration(self) -> chrono::Duration {
+        match self {
+            Self::Hour => chrono::Duration::hours(1),
+            Self::Day => chrono::Duration::days(1),
+            Self::Week => chrono::Duration::weeks(1),
+        }
+    }
+
+    ///
+    /// Counts the timestamps per bucket of `[from, to)`, including the empty buckets.
+    /// Does in memory, what `generate_series()` does for Postgres.
+    ///
+    pub fn count(
+        self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        timestamps: impl IntoIterator<Item = DateTime<Utc>>,
+    ) -> Vec<VisitsInBucket> {
+        let mut counts = BTreeMap::new();
+        let mut ts = self.truncate(from);
+        while ts < to {
+            counts.insert(ts, 0);
+            ts += self.duration();
+        }
+
+        for ts in timestamps {
+            *counts.entry(self.truncate(ts)).or_default() += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(ts, visits)| VisitsInBucket { ts, visits })
+            .collect()
+    }
+}
+
+/// Attribute of the visits to group them by
+#[derive(Debug, Clone, Copy)]
+pub enum VisitDimension {
+    ReferrerHost,
+    Browser,
+    Country,
+}
+
+impl VisitDimension {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ReferrerHost => "referrer_host",
+            Self::Browser => "browser",
+            Self::Country => "country",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct VisitsInBucket {
+    /// start of the bucket
+    pub ts: DateTime<Utc>,
+    pub visits: i64,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct VisitsByKey {
+    /// `None` if the attribute was not known (e.g. no referrer for direct visits)
+    pub key: Option<String>,
+    pub visits: i64,
+}
+
+impl VisitsByKey {
+    ///
+    /// The most frequent keys first, like `Order By visits Desc, key` (where nulls are last).
+    /// For stores, which cannot group by [`VisitDimension`] themselves.
+    ///
+    pub fn top(counts: HashMap<Option<String>, i64>, limit: i64) -> Vec<Self> {
+        let mut counts: Vec<_> = counts.into_iter().map(|(key, visits)| Self { key, visits }).collect();
+        counts.sort_by(|a, b| {
+            b.visits
+                .cmp(&a.visits)
+                .then_with(|| a.key.is_none().cmp(&b.key.is_none()))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        counts.truncate(limit.max(0) as usize);
+        counts
+    }
+}
+
+/// Lowercase host of the referrer URL, cf. the `referrer_host` dimension
+pub fn referrer_host(referrer: &str) -> Option<String> {
+    url::Url::parse(referrer)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+}
+
+/// Details about the visitor, cf. [`crate::visitor::visit_from_request`]
+#[derive(Debug, Clone)]
+pub struct NewVisit {
+    /// time of the visit, which might be a bit before it is written to the database
+    pub ts: DateTime<Utc>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub accept_language: Option<String>,
+    pub country: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub device: Option<String>,
 }
 
 impl LinkVisit {
@@ -19,15 +188,156 @@ impl LinkVisit {
             .map(|row| row.cnt.unwrap_or(0))
     }
 
+    ///
+    /// Inserts many visits with a single statement, cf. [`crate::visit_queue::VisitQueue`]
+    ///
+    #[instrument(skip_all, fields(visits = visits.len()))]
+    pub async fn insert_batch(pool: &PgPool, visits: &[(i32, NewVisit)]) -> anyhow::Result<u64> {
+        // one array per column, so the amount of bind parameters does not depend on the batch size
+        let mut link_ids = Vec::with_capacity(visits.len());
+        let mut tss = Vec::with_capacity(visits.len());
+        let mut referrers = Vec::with_capacity(visits.len());
+        let mut user_agents = Vec::with_capacity(visits.len());
+        let mut ips = Vec::with_capacity(visits.len());
+        let mut accept_languages = Vec::with_capacity(visits.len());
+        let mut countries = Vec::with_capacity(visits.len());
+        let mut browsers = Vec::with_capacity(visits.len());
+        let mut oss = Vec::with_capacity(visits.len());
+        let mut devices = Vec::with_capacity(visits.len());
+        for (link_id, visit) in visits {
+            link_ids.push(*link_id);
+            tss.push(visit.ts);
+            referrers.push(visit.referrer.clone());
+            user_agents.push(visit.user_agent.clone());
+            ips.push(visit.ip.clone());
+            accept_languages.push(visit.accept_language.clone());
+            countries.push(visit.country.clone());
+            browsers.push(visit.browser.clone());
+            oss.push(visit.os.clone());
+            devices.push(visit.device.clone());
+        }
+
+        // visits of links deleted in the meantime are skipped, they would fail the whole batch on the foreign key
+        query!(
+            r#"Insert Into link_visits (link_id, ts, referrer, user_agent, ip, accept_language, country, browser, os, device)
+                Select v.* From unnest(
+                    $1::int4[], $2::timestamptz[], $3::text[], $4::text[], $5::text[],
+                    $6::text[], $7::text[], $8::text[], $9::text[], $10::text[]
+                ) As v (link_id, ts, referrer, user_agent, ip, accept_language, country, browser, os, device)
+                Join links Using (link_id)"#,
+            &link_ids,
+            &tss,
+            &referrers as &[Option<String>],
+            &user_agents as &[Option<String>],
+            &ips as &[Option<String>],
+            &accept_languages as &[Option<String>],
+            &countries as &[Option<String>],
+            &browsers as &[Option<String>],
+            &oss as &[Option<String>],
+            &devices as &[Option<String>],
+        )
+        .execute(pool)
+        .await
+        .context("Failed to insert batch of visits")
+        .map(|result| result.rows_affected())
+    }
+
+    ///
+    /// Counts the visits of a link in `[from, to)` per time bucket. Buckets without visits are included with 0.
+    ///
+    #[instrument(skip(pool))]
+    pub async fn count_per_bucket(
+        pool: &PgPool,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: TimeBucket,
+    ) -> anyhow::Result<Vec<VisitsInBucket>> {
+        query_as!(
+            VisitsInBucket,
+            r#"Select b.ts as "ts!", count(v.ts) as "visits!"
+                From generate_series(
+                    date_trunc($4, $2::timestamptz, 'UTC'),
+                    $3::timestamptz - interval '1 microsecond',
+                    ('1 ' || $4)::interval
+                ) As b(ts)
+                Left Join link_visits v
+                    On v.link_id = $1 And v.ts >= $2 And v.ts < $3 And date_trunc($4, v.ts, 'UTC') = b.ts
+                Group By b.ts
+                Order By b.ts"#,
+            link_id,
+            from,
+            to,
+            bucket.as_str(),
+        )
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("Failed to count visits over time for {link_id}"))
+    }
+
+    ///
+    /// Counts the visits of a link in `[from, to)` grouped by the `dimension`, most frequent values first.
+    ///
     #[instrument(skip(pool))]
-    pub async fn mark_visit(pool: &PgPool, link_id: i32) -> anyhow::Result<LinkVisit> {
+    pub async fn count_by(
+        pool: &PgPool,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        dimension: VisitDimension,
+        limit: i64,
+    ) -> anyhow::Result<Vec<VisitsByKey>> {
         query_as!(
-            LinkVisit,
-            "Insert Into link_visits (link_id) Values ($1) Returning *",
+            VisitsByKey,
+            r#"Select
+                    Case $4
+                        When 'referrer_host' Then lower(substring(referrer From '^[a-zA-Z][a-zA-Z0-9+.-]*://([^/:?#]+)'))
+                        When 'browser' Then browser
+                        When 'country' Then country
+                    End As key,
+                    count(*) as "visits!"
+                From link_visits
+                Where link_id = $1 And ts >= $2 And ts < $3
+                Group By 1
+                Order By 2 Desc, 1
+                Limit $5"#,
             link_id,
+            from,
+            to,
+            dimension.as_str(),
+            limit,
         )
-        .fetch_one(pool)
+        .fetch_all(pool)
         .await
-        .with_context(|| format!("Failed to mark visit for {link_id}"))
+        .with_context(|| format!("Failed to count visits by {} for {link_id}", dimension.as_str()))
+    }
+
+    pub fn export(
+        pool: &PgPool,
+        scope: OwnerScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportStream<ExportedVisit> {
+        let pool = pool.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut rows = query_as!(
+                ExportedVisit,
+                r#"Select v.link_id, l.code, v.ts, v.referrer, v.user_agent, v.ip, v.accept_language,
+                        v.country, v.browser, v.os, v.device
+                    From link_visits v
+                    Join links l On l.link_id = v.link_id
+                    Where ($1::int4 Is Null Or l.owner_id = $1)
+                        And ($2::timestamptz Is Null Or v.ts >= $2)
+                        And ($3::timestamptz Is Null Or v.ts < $3)
+                    Order By v.ts"#,
+                scope.owner_id(),
+                from,
+                to,
+            )
+            .fetch(&pool);
+            while let Some(visit) = rows.try_next().await.context("Failed to export visits")? {
+                yield visit;
+            }
+        })
     }
 }
@@ -1,16 +1,93 @@
 use anyhow::Context;
 use chrono::{DateTime, Utc};
-use nanoid::nanoid;
+use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
-use sqlx::{query_as, FromRow, PgPool};
+use sqlx::{query, query_as, FromRow, PgExecutor, PgPool};
 use tracing::instrument;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+use super::{
+    owners::OwnerScope,
+    store::{ExportStream, VisitStore},
+};
+use crate::redirect::RedirectType;
+
+/// Postgres error code for `unique_violation`
+///     cf. https://www.postgresql.org/docs/current/errcodes-appendix.html
+const PG_UNIQUE_VIOLATION: &str = "23505";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CreateLinkError {
+    #[error("Code '{0}' is already taken")]
+    CodeTaken(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
 pub struct Link {
     pub link_id: i32,
     pub code: String,
     pub url: String,
     pub created_at: DateTime<Utc>,
+    /// the link stops working after this point in time
+    pub expires_at: Option<DateTime<Utc>>,
+    /// the link stops working after this many visits
+    pub max_visits: Option<i32>,
+    /// HTTP status code to redirect with, cf. [`RedirectType`]
+    #[schema(value_type = Option<RedirectType>)]
+    pub redirect_type: Option<i16>,
+    pub owner_id: i32,
+    /// `None` for the default domain, cf. [`super::domains::Domain`]
+    pub domain_id: Option<i32>,
+    /// visitors have to enter the password first, cf. [`crate::password`] - never handed out
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    /// shown on the preview page
+    pub title: Option<String>,
+    /// visitors see a preview page with the target first, cf. [`crate::templates::Templates::preview`]
+    pub preview: bool,
+}
+
+/// A [`Link`] together with its number of visits, as exported
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ExportedLink {
+    pub link_id: i32,
+    pub code: String,
+    /// host of the domain, `None` for the default domain
+    pub domain: Option<String>,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_visits: Option<i32>,
+    pub redirect_type: Option<i16>,
+    pub owner_id: i32,
+    pub visits: i64,
+}
+
+/// Data needed to create a new [`Link`]
+#[derive(Debug, Clone, Default)]
+pub struct NewLink {
+    pub url: String,
+    /// generated, if not given
+    pub code: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_visits: Option<i32>,
+    /// use the server default, if not given
+    pub redirect_type: Option<RedirectType>,
+    pub owner_id: i32,
+    /// `None` for the default domain
+    pub domain_id: Option<i32>,
+    pub password_hash: Option<String>,
+    pub title: Option<String>,
+    pub preview: bool,
+}
+
+/// Changes to an existing [`Link`], `None` leaves the attribute untouched
+#[derive(Debug, Clone, Default)]
+pub struct LinkChanges {
+    pub url: Option<String>,
+    pub redirect_type: Option<RedirectType>,
 }
 
 impl Link {
@@ -23,25 +100,207 @@ impl Link {
     }
 
     #[instrument(skip(pool))]
-    pub async fn find_by_code(pool: &PgPool, code: &str) -> anyhow::Result<Option<Self>> {
-        query_as!(Self, "Select * From links Where code = $1 Limit 1", code)
-            .fetch_optional(pool)
-            .await
-            .context("Error fetching link by id")
+    pub async fn find_by_code(
+        pool: &PgPool,
+        scope: OwnerScope,
+        domain_id: Option<i32>,
+        code: &str,
+    ) -> anyhow::Result<Option<Self>> {
+        // like the unique index, so it can be used
+        query_as!(
+            Self,
+            r#"Select * From links
+                Where coalesce(domain_id, 0) = coalesce($3, 0) And code = $1 And ($2::int4 Is Null Or owner_id = $2)
+                Limit 1"#,
+            code,
+            scope.owner_id(),
+            domain_id,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Error fetching link by code")
     }
 
-    #[instrument(skip_all)]
-    pub async fn create<S: ToString>(pool: &PgPool, url: &str, code: Option<S>) -> anyhow::Result<Self> {
-        let code = code.map(|s| s.to_string()).unwrap_or_else(|| nanoid!());
+    #[instrument(skip(executor))]
+    pub async fn next_id(executor: impl PgExecutor<'_>) -> anyhow::Result<i32> {
+        query!(r#"Select nextval(pg_get_serial_sequence('links', 'link_id'))::int4 as "link_id!""#)
+            .fetch_one(executor)
+            .await
+            .context("Failed to reserve link id")
+            .map(|row| row.link_id)
+    }
 
+    #[instrument(skip(executor, new))]
+    pub async fn insert(
+        executor: impl PgExecutor<'_>,
+        link_id: Option<i32>,
+        code: String,
+        new: &NewLink,
+    ) -> Result<Self, CreateLinkError> {
         query_as!(
             Self,
-            r#"Insert Into links (code, url) Values ($1, $2) Returning *"#,
+            r#"Insert Into links (link_id, code, url, expires_at, max_visits, redirect_type, owner_id, domain_id, password_hash,
+                    title, preview)
+                Values (coalesce($1, nextval(pg_get_serial_sequence('links', 'link_id'))::int4), $2, $3, $4, $5, $6, $7, $8, $9,
+                    $10, $11)
+                Returning *"#,
+            link_id,
             code,
-            url
+            new.url,
+            new.expires_at,
+            new.max_visits,
+            new.redirect_type.map(i16::from),
+            new.owner_id,
+            new.domain_id,
+            new.password_hash,
+            new.title,
+            new.preview,
+        )
+        .fetch_one(executor)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(ref db_err) if db_err.code().as_deref() == Some(PG_UNIQUE_VIOLATION) => {
+                CreateLinkError::CodeTaken(code)
+            }
+            err => anyhow::Error::new(err).context("Failed to create new link").into(),
+        })
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn list(pool: &PgPool, scope: OwnerScope, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Self>> {
+        query_as!(
+            Self,
+            r#"Select * From links
+                Where link_id > $1 And ($3::int4 Is Null Or owner_id = $3)
+                Order By link_id
+                Limit $2"#,
+            after.unwrap_or(0),
+            limit,
+            scope.owner_id(),
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error listing links")
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn update(
+        pool: &PgPool,
+        scope: OwnerScope,
+        link_id: i32,
+        changes: &LinkChanges,
+    ) -> anyhow::Result<Option<Self>> {
+        query_as!(
+            Self,
+            r#"Update links
+                Set url = coalesce($2, url), redirect_type = coalesce($3, redirect_type)
+                Where link_id = $1 And ($4::int4 Is Null Or owner_id = $4)
+                Returning *"#,
+            link_id,
+            changes.url,
+            changes.redirect_type.map(i16::from),
+            scope.owner_id(),
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("Failed to update link {link_id}"))
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn delete(pool: &PgPool, scope: OwnerScope, link_id: i32) -> anyhow::Result<bool> {
+        let mut tx = pool.begin().await.context("Failed to start transaction")?;
+
+        // visits reference the link, so they have to go first
+        query!(
+            r#"Delete From link_visits Where link_id In (
+                Select link_id From links Where link_id = $1 And ($2::int4 Is Null Or owner_id = $2)
+            )"#,
+            link_id,
+            scope.owner_id(),
+        )
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("Failed to delete visits of link {link_id}"))?;
+        let result = query!(
+            "Delete From links Where link_id = $1 And ($2::int4 Is Null Or owner_id = $2)",
+            link_id,
+            scope.owner_id(),
+        )
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("Failed to delete link {link_id}"))?;
+
+        tx.commit().await.context("Failed to commit link deletion")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn export(pool: &PgPool, scope: OwnerScope) -> ExportStream<ExportedLink> {
+        let pool = pool.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut rows = query_as!(
+                ExportedLink,
+                r#"Select l.link_id, l.code, d.host as "domain?", l.url, l.created_at, l.expires_at, l.max_visits,
+                        l.redirect_type, l.owner_id,
+                        (Select count(*) From link_visits v Where v.link_id = l.link_id) as "visits!"
+                    From links l
+                    Left Join domains d On d.domain_id = l.domain_id
+                    Where $1::int4 Is Null Or l.owner_id = $1
+                    Order By l.link_id"#,
+                scope.owner_id(),
+            )
+            .fetch(&pool);
+            while let Some(link) = rows.try_next().await.context("Failed to export links")? {
+                yield link;
+            }
+        })
+    }
+
+    ///
+    /// The redirect type of this link, if it has a valid one
+    ///
+    pub fn redirect_type(&self) -> Option<RedirectType> {
+        self.redirect_type
+            .and_then(|status| RedirectType::try_from(status as u16).ok())
+    }
+
+    ///
+    /// Checks whether the link passed its expiry date or used up its visits.
+    /// Visits are written in batches, so `max_visits` might be exceeded by the few visits still queued.
+    ///
+    #[instrument(skip_all, fields(link_id = self.link_id))]
+    pub async fn is_expired(&self, visits: &dyn VisitStore) -> anyhow::Result<bool> {
+        if self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Ok(true);
+        }
+
+        match self.max_visits {
+            Some(max_visits) => Ok(visits.count_for_link(self.link_id).await? >= max_visits as i64),
+            None => Ok(false),
+        }
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn delete_expired(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+        // foreign keys are only checked at the end of the statement, so both deletes can go in one
+        let row = query!(
+            r#"With expired As (
+                    Select l.link_id From links l
+                    Left Join link_visits v On v.link_id = l.link_id
+                    Group By l.link_id
+                    Having l.expires_at < $1
+                        Or (l.max_visits Is Not Null And count(v.ts) >= l.max_visits And max(v.ts) < $1)
+                ), deleted_visits As (
+                    Delete From link_visits Where link_id In (Select link_id From expired)
+                ), deleted_links As (
+                    Delete From links Where link_id In (Select link_id From expired) Returning link_id
+                )
+                Select count(*) as "cnt!" From deleted_links"#,
+            before
         )
         .fetch_one(pool)
         .await
-        .context("Failed to create new link")
+        .context("Failed to delete expired links")?;
+
+        Ok(row.cnt as u64)
     }
 }
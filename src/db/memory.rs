@@ -0,0 +1,458 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+
+use super::{
+    api_keys::{hash_key, ApiKey},
+    domains::Domain,
+    link_visit::{
+        referrer_host, ExportedVisit, LinkVisit, NewVisit, TimeBucket, VisitDimension, VisitsByKey, VisitsInBucket,
+    },
+    links::{CreateLinkError, ExportedLink, Link, LinkChanges, NewLink},
+    owners::{Owner, OwnerScope},
+    store::{self, ApiKeyStore, DomainStore, ExportStream, LinkInserts, LinkStore, VisitStore},
+};
+use crate::codes::CodeGenerator;
+
+///
+/// Keeps everything in memory, so the service runs without a database (cf. `--storage memory`).
+/// Nothing survives a restart and nothing is shared between instances.
+///
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    data: RwLock<Data>,
+}
+
+#[derive(Debug, Default)]
+struct Data {
+    /// by id, so listing is ordered like in the database
+    links: BTreeMap<i32, Link>,
+    /// by domain and code, like the unique index in the database
+    link_ids_by_code: HashMap<(Option<i32>, String), i32>,
+    last_link_id: i32,
+    visits: Vec<LinkVisit>,
+    domains: Vec<Domain>,
+    owners: Vec<Owner>,
+    /// together with the hash of the key
+    api_keys: Vec<(ApiKey, String)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Data> {
+        self.data.read().expect("memory store lock poisoned")
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Data> {
+        self.data.write().expect("memory store lock poisoned")
+    }
+}
+
+impl Data {
+    fn next_link_id(&mut self) -> i32 {
+        self.last_link_id += 1;
+        self.last_link_id
+    }
+
+    fn insert_link(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        let key = (new.domain_id, code);
+        if self.link_ids_by_code.contains_key(&key) {
+            return Err(CreateLinkError::CodeTaken(key.1));
+        }
+
+        let link_id = link_id.unwrap_or_else(|| self.next_link_id());
+        let link = Link {
+            link_id,
+            code: key.1.clone(),
+            url: new.url.clone(),
+            created_at: Utc::now(),
+            expires_at: new.expires_at,
+            max_visits: new.max_visits,
+            redirect_type: new.redirect_type.map(i16::from),
+            owner_id: new.owner_id,
+            domain_id: new.domain_id,
+            password_hash: new.password_hash.clone(),
+            title: new.title.clone(),
+            preview: new.preview,
+        };
+        self.link_ids_by_code.insert(key, link_id);
+        self.links.insert(link_id, link.clone());
+        Ok(link)
+    }
+}
+
+#[async_trait]
+impl LinkInserts for Data {
+    async fn next_id(&mut self) -> anyhow::Result<i32> {
+        Ok(self.next_link_id())
+    }
+
+    async fn insert(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        self.insert_link(link_id, code, new)
+    }
+}
+
+fn in_scope(scope: OwnerScope, link: &Link) -> bool {
+    scope.owner_id().is_none_or(|owner_id| link.owner_id == owner_id)
+}
+
+#[async_trait]
+impl LinkStore for MemoryStore {
+    async fn find_by_code(
+        &self,
+        scope: OwnerScope,
+        domain_id: Option<i32>,
+        code: &str,
+    ) -> anyhow::Result<Option<Link>> {
+        let data = self.read();
+        Ok(data
+            .link_ids_by_code
+            .get(&(domain_id, code.to_string()))
+            .and_then(|link_id| data.links.get(link_id))
+            .filter(|link| in_scope(scope, link))
+            .cloned())
+    }
+
+    async fn next_id(&self) -> anyhow::Result<i32> {
+        Ok(self.write().next_link_id())
+    }
+
+    async fn insert(&self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        self.write().insert_link(link_id, code, new)
+    }
+
+    async fn list(&self, scope: OwnerScope, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Link>> {
+        let data = self.read();
+        Ok(data
+            .links
+            .range(after.unwrap_or(0) + 1..)
+            .map(|(_, link)| link)
+            .filter(|link| in_scope(scope, link))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn update(&self, scope: OwnerScope, link_id: i32, changes: &LinkChanges) -> anyhow::Result<Option<Link>> {
+        let mut data = self.write();
+        let Some(link) = data.links.get_mut(&link_id).filter(|link| in_scope(scope, link)) else {
+            return Ok(None);
+        };
+        if let Some(ref url) = changes.url {
+            link.url = url.clone();
+        }
+        if let Some(redirect_type) = changes.redirect_type {
+            link.redirect_type = Some(redirect_type.into());
+        }
+        Ok(Some(link.clone()))
+    }
+
+    async fn delete(&self, scope: OwnerScope, link_id: i32) -> anyhow::Result<bool> {
+        let mut data = self.write();
+        if !data.links.get(&link_id).is_some_and(|link| in_scope(scope, link)) {
+            return Ok(false);
+        }
+        if let Some(link) = data.links.remove(&link_id) {
+            data.link_ids_by_code.remove(&(link.domain_id, link.code));
+        }
+        data.visits.retain(|visit| visit.link_id != link_id);
+        Ok(true)
+    }
+
+    async fn delete_expired(&self, before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut data = self.write();
+
+        let expired: Vec<i32> = data
+            .links
+            .values()
+            .filter(|link| {
+                if link.expires_at.is_some_and(|expires_at| expires_at < before) {
+                    return true;
+                }
+                let Some(max_visits) = link.max_visits else {
+                    return false;
+                };
+                let visits: Vec<_> = data.visits.iter().filter(|v| v.link_id == link.link_id).collect();
+                visits.len() as i64 >= max_visits as i64 && visits.iter().all(|visit| visit.ts < before)
+            })
+            .map(|link| link.link_id)
+            .collect();
+
+        for link_id in &expired {
+            if let Some(link) = data.links.remove(link_id) {
+                data.link_ids_by_code.remove(&(link.domain_id, link.code));
+            }
+        }
+        data.visits.retain(|visit| !expired.contains(&visit.link_id));
+        Ok(expired.len() as u64)
+    }
+
+    fn export_links(&self, scope: OwnerScope) -> ExportStream<ExportedLink> {
+        // everything is in memory anyway, so a snapshot does not hurt
+        let data = self.read();
+        let links: Vec<_> = data
+            .links
+            .values()
+            .filter(|link| in_scope(scope, link))
+            .map(|link| ExportedLink {
+                link_id: link.link_id,
+                code: link.code.clone(),
+                domain: data
+                    .domains
+                    .iter()
+                    .find(|domain| Some(domain.domain_id) == link.domain_id)
+                    .map(|domain| domain.host.clone()),
+                url: link.url.clone(),
+                created_at: link.created_at,
+                expires_at: link.expires_at,
+                max_visits: link.max_visits,
+                redirect_type: link.redirect_type,
+                owner_id: link.owner_id,
+                visits: data.visits.iter().filter(|v| v.link_id == link.link_id).count() as i64,
+            })
+            .map(Ok)
+            .collect();
+        Box::pin(futures::stream::iter(links))
+    }
+
+    async fn create_all(
+        &self,
+        generator: &CodeGenerator,
+        new: &[NewLink],
+    ) -> anyhow::Result<Result<Vec<Link>, (usize, CreateLinkError)>> {
+        // holding the lock throughout, nobody sees some of the links before all are created. This works, since
+        // inserting into memory never has to wait, so the future is done right away.
+        let mut data = self.write();
+        let last_link_id = data.last_link_id;
+        let created = store::create_each(&mut *data, generator, new)
+            .now_or_never()
+            .context("Creating links in memory must not wait")?;
+
+        if created.is_err() {
+            // all links created since have a higher id
+            for link in data.links.split_off(&(last_link_id + 1)).into_values() {
+                data.link_ids_by_code.remove(&(link.domain_id, link.code));
+            }
+        }
+        Ok(created)
+    }
+}
+
+#[async_trait]
+impl VisitStore for MemoryStore {
+    async fn count_for_link(&self, link_id: i32) -> anyhow::Result<i64> {
+        Ok(self.read().visits.iter().filter(|v| v.link_id == link_id).count() as i64)
+    }
+
+    async fn insert_batch(&self, visits: &[(i32, NewVisit)]) -> anyhow::Result<u64> {
+        let mut data = self.write();
+        let mut inserted = 0;
+        for (link_id, visit) in visits {
+            // like the databases, which skip visits of links deleted in the meantime
+            if !data.links.contains_key(link_id) {
+                continue;
+            }
+            data.visits.push(LinkVisit {
+                link_id: *link_id,
+                ts: visit.ts,
+                referrer: visit.referrer.clone(),
+                user_agent: visit.user_agent.clone(),
+                ip: visit.ip.clone(),
+                accept_language: visit.accept_language.clone(),
+                country: visit.country.clone(),
+                browser: visit.browser.clone(),
+                os: visit.os.clone(),
+                device: visit.device.clone(),
+            });
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+
+    async fn count_per_bucket(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: TimeBucket,
+    ) -> anyhow::Result<Vec<VisitsInBucket>> {
+        let data = self.read();
+        Ok(bucket.count(from, to, visits_between(&data, link_id, from, to).map(|visit| visit.ts)))
+    }
+
+    async fn count_by(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        dimension: VisitDimension,
+        limit: i64,
+    ) -> anyhow::Result<Vec<VisitsByKey>> {
+        let data = self.read();
+        let mut counts: HashMap<Option<String>, i64> = HashMap::new();
+        for visit in visits_between(&data, link_id, from, to) {
+            let key = match dimension {
+                VisitDimension::ReferrerHost => visit.referrer.as_deref().and_then(referrer_host),
+                VisitDimension::Browser => visit.browser.clone(),
+                VisitDimension::Country => visit.country.clone(),
+            };
+            *counts.entry(key).or_default() += 1;
+        }
+
+        Ok(VisitsByKey::top(counts, limit))
+    }
+
+    fn export_visits(
+        &self,
+        scope: OwnerScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportStream<ExportedVisit> {
+        let data = self.read();
+        let mut visits: Vec<_> = data
+            .visits
+            .iter()
+            .filter(|visit| from.is_none_or(|from| visit.ts >= from) && to.is_none_or(|to| visit.ts < to))
+            .filter_map(|visit| {
+                let link = data.links.get(&visit.link_id).filter(|link| in_scope(scope, link))?;
+                Some(ExportedVisit {
+                    link_id: visit.link_id,
+                    code: link.code.clone(),
+                    ts: visit.ts,
+                    referrer: visit.referrer.clone(),
+                    user_agent: visit.user_agent.clone(),
+                    ip: visit.ip.clone(),
+                    accept_language: visit.accept_language.clone(),
+                    country: visit.country.clone(),
+                    browser: visit.browser.clone(),
+                    os: visit.os.clone(),
+                    device: visit.device.clone(),
+                })
+            })
+            .collect();
+        // batches are not necessarily written in order
+        visits.sort_by_key(|visit| visit.ts);
+        Box::pin(futures::stream::iter(visits.into_iter().map(Ok)))
+    }
+}
+
+fn visits_between(
+    data: &Data,
+    link_id: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> impl Iterator<Item = &LinkVisit> {
+    data.visits
+        .iter()
+        .filter(move |visit| visit.link_id == link_id && visit.ts >= from && visit.ts < to)
+}
+
+#[async_trait]
+impl DomainStore for MemoryStore {
+    async fn list_domains(&self) -> anyhow::Result<Vec<Domain>> {
+        Ok(self.read().domains.clone())
+    }
+
+    async fn upsert_domain(&self, host: &str, fallback_url: Option<&str>) -> anyhow::Result<Domain> {
+        let mut data = self.write();
+        if let Some(domain) = data.domains.iter_mut().find(|domain| domain.host == host) {
+            domain.fallback_url = fallback_url.map(str::to_string);
+            return Ok(domain.clone());
+        }
+
+        let domain = Domain {
+            domain_id: data.domains.len() as i32 + 1,
+            host: host.to_string(),
+            fallback_url: fallback_url.map(str::to_string),
+            created_at: Utc::now(),
+        };
+        data.domains.push(domain.clone());
+        Ok(domain)
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryStore {
+    async fn find_owner(&self, owner_id: i32) -> anyhow::Result<Option<Owner>> {
+        Ok(self
+            .read()
+            .owners
+            .iter()
+            .find(|owner| owner.owner_id == owner_id)
+            .cloned())
+    }
+
+    async fn find_or_create_owner(&self, name: &str, is_admin: bool) -> anyhow::Result<Owner> {
+        let mut data = self.write();
+        if let Some(owner) = data.owners.iter_mut().find(|owner| owner.name == name) {
+            owner.is_admin |= is_admin;
+            return Ok(owner.clone());
+        }
+
+        let owner = Owner {
+            owner_id: data.owners.len() as i32 + 1,
+            name: name.to_string(),
+            is_admin,
+            created_at: Utc::now(),
+        };
+        data.owners.push(owner.clone());
+        Ok(owner)
+    }
+
+    async fn insert_api_key(&self, owner_id: i32, name: &str, prefix: &str, key_hash: &str) -> anyhow::Result<ApiKey> {
+        let mut data = self.write();
+        let api_key = ApiKey {
+            api_key_id: data.api_keys.len() as i32 + 1,
+            owner_id,
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        data.api_keys.push((api_key.clone(), key_hash.to_string()));
+        Ok(api_key)
+    }
+
+    async fn list_api_keys(&self) -> anyhow::Result<Vec<ApiKey>> {
+        Ok(self
+            .read()
+            .api_keys
+            .iter()
+            .map(|(api_key, _)| api_key.clone())
+            .collect())
+    }
+
+    async fn find_active_api_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let key_hash = hash_key(key);
+        Ok(self
+            .read()
+            .api_keys
+            .iter()
+            .find(|(api_key, hash)| *hash == key_hash && api_key.revoked_at.is_none())
+            .map(|(api_key, _)| api_key.clone()))
+    }
+
+    async fn revoke_api_key(&self, api_key_id: i32) -> anyhow::Result<bool> {
+        let mut data = self.write();
+        match data
+            .api_keys
+            .iter_mut()
+            .find(|(api_key, _)| api_key.api_key_id == api_key_id && api_key.revoked_at.is_none())
+        {
+            Some((api_key, _)) => {
+                api_key.revoked_at = Some(Utc::now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -1,2 +1,9 @@
+pub mod api_keys;
+pub mod domains;
 pub mod link_visit;
 pub mod links;
+pub mod memory;
+pub mod owners;
+pub mod postgres;
+pub mod sqlite;
+pub mod store;
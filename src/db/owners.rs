@@ -0,0 +1,74 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{query_as, FromRow, PgPool};
+use tracing::instrument;
+
+///
+/// Owner of links (e.g. a team). API keys act on behalf of their owner.
+///
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Owner {
+    pub owner_id: i32,
+    pub name: String,
+    /// admins can see and manage the links of all owners
+    pub is_admin: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+///
+/// Restricts the links a query can see to the ones of a single owner
+///
+#[derive(Debug, Clone, Copy)]
+pub enum OwnerScope {
+    All,
+    Owner(i32),
+}
+
+impl OwnerScope {
+    /// `None` means all owners, which fits the `$1::int4 Is Null Or owner_id = $1` pattern in queries
+    pub fn owner_id(self) -> Option<i32> {
+        match self {
+            Self::All => None,
+            Self::Owner(owner_id) => Some(owner_id),
+        }
+    }
+}
+
+impl Owner {
+    #[instrument(skip(pool))]
+    pub async fn find_by_id(pool: &PgPool, owner_id: i32) -> anyhow::Result<Option<Self>> {
+        query_as!(Self, "Select * From owners Where owner_id = $1", owner_id)
+            .fetch_optional(pool)
+            .await
+            .with_context(|| format!("Error fetching owner {owner_id}"))
+    }
+
+    ///
+    /// Returns the owner with the given name and creates it, if it does not exist yet.
+    /// Passing `is_admin = true` promotes an existing owner to admin.
+    ///
+    #[instrument(skip(pool))]
+    pub async fn find_or_create(pool: &PgPool, name: &str, is_admin: bool) -> anyhow::Result<Self> {
+        query_as!(
+            Self,
+            r#"Insert Into owners (name, is_admin) Values ($1, $2)
+                On Conflict (name) Do Update Set is_admin = owners.is_admin Or excluded.is_admin
+                Returning *"#,
+            name,
+            is_admin,
+        )
+        .fetch_one(pool)
+        .await
+        .with_context(|| format!("Failed to create owner {name}"))
+    }
+
+    /// Scope of the links, this owner is allowed to see
+    pub fn scope(&self) -> OwnerScope {
+        if self.is_admin {
+            OwnerScope::All
+        } else {
+            OwnerScope::Owner(self.owner_id)
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Connection, PgPool, Postgres, Transaction};
+
+use super::{
+    api_keys::ApiKey,
+    domains::Domain,
+    link_visit::{ExportedVisit, LinkVisit, NewVisit, TimeBucket, VisitDimension, VisitsByKey, VisitsInBucket},
+    links::{CreateLinkError, ExportedLink, Link, LinkChanges, NewLink},
+    owners::{Owner, OwnerScope},
+    store::{self, ApiKeyStore, DomainStore, ExportStream, LinkInserts, LinkStore, VisitStore},
+};
+use crate::codes::CodeGenerator;
+
+///
+/// The stores backed by Postgres, the queries live with the respective types (e.g. [`Link`])
+///
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LinkStore for PgStore {
+    async fn find_by_code(
+        &self,
+        scope: OwnerScope,
+        domain_id: Option<i32>,
+        code: &str,
+    ) -> anyhow::Result<Option<Link>> {
+        Link::find_by_code(&self.pool, scope, domain_id, code).await
+    }
+
+    async fn next_id(&self) -> anyhow::Result<i32> {
+        Link::next_id(&self.pool).await
+    }
+
+    async fn insert(&self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        Link::insert(&self.pool, link_id, code, new).await
+    }
+
+    async fn list(&self, scope: OwnerScope, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Link>> {
+        Link::list(&self.pool, scope, after, limit).await
+    }
+
+    async fn update(&self, scope: OwnerScope, link_id: i32, changes: &LinkChanges) -> anyhow::Result<Option<Link>> {
+        Link::update(&self.pool, scope, link_id, changes).await
+    }
+
+    async fn delete(&self, scope: OwnerScope, link_id: i32) -> anyhow::Result<bool> {
+        Link::delete(&self.pool, scope, link_id).await
+    }
+
+    async fn delete_expired(&self, before: DateTime<Utc>) -> anyhow::Result<u64> {
+        Link::delete_expired(&self.pool, before).await
+    }
+
+    fn export_links(&self, scope: OwnerScope) -> ExportStream<ExportedLink> {
+        Link::export(&self.pool, scope)
+    }
+
+    async fn create_all(
+        &self,
+        generator: &CodeGenerator,
+        new: &[NewLink],
+    ) -> anyhow::Result<Result<Vec<Link>, (usize, CreateLinkError)>> {
+        let mut tx = LinkTransaction(self.pool.begin().await.context("Failed to begin transaction")?);
+        match store::create_each(&mut tx, generator, new).await {
+            Ok(links) => {
+                tx.0.commit().await.context("Failed to commit links")?;
+                Ok(Ok(links))
+            }
+            Err(err) => {
+                tx.0.rollback().await.context("Failed to roll back links")?;
+                Ok(Err(err))
+            }
+        }
+    }
+}
+
+///
+/// Inserts links within a transaction, each in its own savepoint: A taken code fails the insert, but must not
+/// abort the whole transaction, so another code can be tried.
+///
+struct LinkTransaction<'c>(Transaction<'c, Postgres>);
+
+#[async_trait]
+impl LinkInserts for LinkTransaction<'_> {
+    async fn next_id(&mut self) -> anyhow::Result<i32> {
+        Link::next_id(&mut self.0).await
+    }
+
+    async fn insert(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        let mut savepoint = self.0.begin().await.context("Failed to create savepoint")?;
+        match Link::insert(&mut savepoint, link_id, code, new).await {
+            Ok(link) => {
+                savepoint.commit().await.context("Failed to release savepoint")?;
+                Ok(link)
+            }
+            Err(err) => {
+                savepoint.rollback().await.context("Failed to roll back to savepoint")?;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl VisitStore for PgStore {
+    async fn count_for_link(&self, link_id: i32) -> anyhow::Result<i64> {
+        LinkVisit::count_for_link_id(&self.pool, link_id).await
+    }
+
+    async fn insert_batch(&self, visits: &[(i32, NewVisit)]) -> anyhow::Result<u64> {
+        LinkVisit::insert_batch(&self.pool, visits).await
+    }
+
+    async fn count_per_bucket(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: TimeBucket,
+    ) -> anyhow::Result<Vec<VisitsInBucket>> {
+        LinkVisit::count_per_bucket(&self.pool, link_id, from, to, bucket).await
+    }
+
+    async fn count_by(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        dimension: VisitDimension,
+        limit: i64,
+    ) -> anyhow::Result<Vec<VisitsByKey>> {
+        LinkVisit::count_by(&self.pool, link_id, from, to, dimension, limit).await
+    }
+
+    fn export_visits(
+        &self,
+        scope: OwnerScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportStream<ExportedVisit> {
+        LinkVisit::export(&self.pool, scope, from, to)
+    }
+}
+
+#[async_trait]
+impl DomainStore for PgStore {
+    async fn list_domains(&self) -> anyhow::Result<Vec<Domain>> {
+        Domain::list(&self.pool).await
+    }
+
+    async fn upsert_domain(&self, host: &str, fallback_url: Option<&str>) -> anyhow::Result<Domain> {
+        Domain::upsert(&self.pool, host, fallback_url).await
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for PgStore {
+    async fn find_owner(&self, owner_id: i32) -> anyhow::Result<Option<Owner>> {
+        Owner::find_by_id(&self.pool, owner_id).await
+    }
+
+    async fn find_or_create_owner(&self, name: &str, is_admin: bool) -> anyhow::Result<Owner> {
+        Owner::find_or_create(&self.pool, name, is_admin).await
+    }
+
+    async fn insert_api_key(&self, owner_id: i32, name: &str, prefix: &str, key_hash: &str) -> anyhow::Result<ApiKey> {
+        ApiKey::insert(&self.pool, owner_id, name, prefix, key_hash).await
+    }
+
+    async fn list_api_keys(&self) -> anyhow::Result<Vec<ApiKey>> {
+        ApiKey::list(&self.pool).await
+    }
+
+    async fn find_active_api_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>> {
+        ApiKey::find_active(&self.pool, key).await
+    }
+
+    async fn revoke_api_key(&self, api_key_id: i32) -> anyhow::Result<bool> {
+        ApiKey::revoke(&self.pool, api_key_id).await
+    }
+}
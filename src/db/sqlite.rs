@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use sqlx::{query, query_as, query_scalar, QueryBuilder, Sqlite, SqliteExecutor, SqlitePool, Transaction};
+use tracing::instrument;
+
+use super::{
+    api_keys::{hash_key, ApiKey},
+    domains::Domain,
+    link_visit::{referrer_host, ExportedVisit, NewVisit, TimeBucket, VisitDimension, VisitsByKey, VisitsInBucket},
+    links::{CreateLinkError, ExportedLink, Link, LinkChanges, NewLink},
+    owners::{Owner, OwnerScope},
+    store::{self, ApiKeyStore, DomainStore, ExportStream, LinkInserts, LinkStore, VisitStore},
+};
+use crate::codes::CodeGenerator;
+
+/// SQLite extended error code `SQLITE_CONSTRAINT_UNIQUE`
+///     cf. https://www.sqlite.org/rescode.html#constraint_unique
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+
+/// SQLite limits the bind parameters per statement, so batches of visits are inserted in chunks
+const VISITS_PER_INSERT: usize = 1000;
+
+const API_KEY_COLUMNS: &str = "api_key_id, owner_id, name, prefix, created_at, revoked_at";
+
+///
+/// The row returned by a write with `Returning`, which was fetched with `fetch_all`: SQLite only commits the write
+/// once the statement was stepped to its end, which `fetch_one` does not do - so the write would be lost, when a
+/// CLI command exits right after.
+///
+fn returned<T>(rows: Vec<T>) -> anyhow::Result<T> {
+    rows.into_iter().next().context("Write returned no row")
+}
+
+///
+/// The stores backed by SQLite, for running without a database server (cf. `migrations_sqlite/`).
+/// The queries are checked at runtime only, since the query macros can only check against one database.
+///
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+///
+/// Timestamps are stored as text with a fixed number of fractional digits, so they sort like the points in time
+/// (the default encoding drops trailing zeros).
+///
+fn ts(value: DateTime<Utc>) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()
+}
+
+#[async_trait]
+impl LinkStore for SqliteStore {
+    #[instrument(skip(self))]
+    async fn find_by_code(
+        &self,
+        scope: OwnerScope,
+        domain_id: Option<i32>,
+        code: &str,
+    ) -> anyhow::Result<Option<Link>> {
+        // like the unique index, so it can be used
+        query_as(
+            r#"Select * From links
+                Where coalesce(domain_id, 0) = coalesce(?3, 0) And code = ?1 And (?2 Is Null Or owner_id = ?2)
+                Limit 1"#,
+        )
+        .bind(code)
+        .bind(scope.owner_id())
+        .bind(domain_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Error fetching link by code")
+    }
+
+    #[instrument(skip(self))]
+    async fn next_id(&self) -> anyhow::Result<i32> {
+        next_link_id(&self.pool).await
+    }
+
+    async fn insert(&self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        insert_link(&self.pool, link_id, code, new).await
+    }
+
+    #[instrument(skip(self))]
+    async fn list(&self, scope: OwnerScope, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Link>> {
+        query_as(
+            r#"Select * From links
+                Where link_id > ?1 And (?3 Is Null Or owner_id = ?3)
+                Order By link_id
+                Limit ?2"#,
+        )
+        .bind(after.unwrap_or(0))
+        .bind(limit)
+        .bind(scope.owner_id())
+        .fetch_all(&self.pool)
+        .await
+        .context("Error listing links")
+    }
+
+    #[instrument(skip(self))]
+    async fn update(&self, scope: OwnerScope, link_id: i32, changes: &LinkChanges) -> anyhow::Result<Option<Link>> {
+        query_as(
+            r#"Update links
+                Set url = coalesce(?2, url), redirect_type = coalesce(?3, redirect_type)
+                Where link_id = ?1 And (?4 Is Null Or owner_id = ?4)
+                Returning *"#,
+        )
+        .bind(link_id)
+        .bind(&changes.url)
+        .bind(changes.redirect_type.map(i16::from))
+        .bind(scope.owner_id())
+        .fetch_all(&self.pool)
+        .await
+        .map(|links| links.into_iter().next())
+        .with_context(|| format!("Failed to update link {link_id}"))
+    }
+
+    #[instrument(skip(self))]
+    async fn delete(&self, scope: OwnerScope, link_id: i32) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await.context("Failed to start transaction")?;
+
+        // visits reference the link, so they have to go first
+        query(
+            r#"Delete From link_visits Where link_id In (
+                Select link_id From links Where link_id = ?1 And (?2 Is Null Or owner_id = ?2)
+            )"#,
+        )
+        .bind(link_id)
+        .bind(scope.owner_id())
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("Failed to delete visits of link {link_id}"))?;
+        let result = query("Delete From links Where link_id = ?1 And (?2 Is Null Or owner_id = ?2)")
+            .bind(link_id)
+            .bind(scope.owner_id())
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Failed to delete link {link_id}"))?;
+
+        tx.commit().await.context("Failed to commit link deletion")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_expired(&self, before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await.context("Failed to start transaction")?;
+
+        // deleting the visits changes, which links are used up - so collect them first
+        let expired: Vec<i32> = query_scalar(
+            r#"Select l.link_id From links l
+                Left Join link_visits v On v.link_id = l.link_id
+                Group By l.link_id
+                Having l.expires_at < ?1
+                    Or (l.max_visits Is Not Null And count(v.ts) >= l.max_visits And max(v.ts) < ?1)"#,
+        )
+        .bind(ts(before))
+        .fetch_all(&mut tx)
+        .await
+        .context("Failed to find expired links")?;
+        let expired_json = serde_json::to_string(&expired)?;
+
+        query("Delete From link_visits Where link_id In (Select value From json_each(?))")
+            .bind(&expired_json)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete visits of expired links")?;
+        let result = query("Delete From links Where link_id In (Select value From json_each(?))")
+            .bind(&expired_json)
+            .execute(&mut tx)
+            .await
+            .context("Failed to delete expired links")?;
+
+        tx.commit()
+            .await
+            .context("Failed to commit deletion of expired links")?;
+        Ok(result.rows_affected())
+    }
+
+    fn export_links(&self, scope: OwnerScope) -> ExportStream<ExportedLink> {
+        let pool = self.pool.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut rows = query_as(
+                r#"Select l.*, d.host As domain,
+                        (Select count(*) From link_visits v Where v.link_id = l.link_id) As visits
+                    From links l
+                    Left Join domains d On d.domain_id = l.domain_id
+                    Where ?1 Is Null Or l.owner_id = ?1
+                    Order By l.link_id"#,
+            )
+            .bind(scope.owner_id())
+            .fetch(&pool);
+            while let Some(link) = rows.try_next().await.context("Failed to export links")? {
+                yield link;
+            }
+        })
+    }
+
+    async fn create_all(
+        &self,
+        generator: &CodeGenerator,
+        new: &[NewLink],
+    ) -> anyhow::Result<Result<Vec<Link>, (usize, CreateLinkError)>> {
+        let mut tx = LinkTransaction(self.pool.begin().await.context("Failed to begin transaction")?);
+        match store::create_each(&mut tx, generator, new).await {
+            Ok(links) => {
+                tx.0.commit().await.context("Failed to commit links")?;
+                Ok(Ok(links))
+            }
+            Err(err) => {
+                tx.0.rollback().await.context("Failed to roll back links")?;
+                Ok(Err(err))
+            }
+        }
+    }
+}
+
+async fn next_link_id(executor: impl SqliteExecutor<'_>) -> anyhow::Result<i32> {
+    // the counter of the AUTOINCREMENT column, which is never handed out twice
+    query_scalar("Update sqlite_sequence Set seq = seq + 1 Where name = 'links' Returning seq")
+        .fetch_all(executor)
+        .await
+        .context("Failed to reserve link id")
+        .and_then(returned)
+}
+
+#[instrument(skip(executor, new))]
+async fn insert_link(
+    executor: impl SqliteExecutor<'_>,
+    link_id: Option<i32>,
+    code: String,
+    new: &NewLink,
+) -> Result<Link, CreateLinkError> {
+    query_as(
+        r#"Insert Into links (link_id, code, url, created_at, expires_at, max_visits, redirect_type, owner_id, domain_id, password_hash,
+                title, preview)
+            Values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            Returning *"#,
+    )
+    .bind(link_id)
+    .bind(&code)
+    .bind(&new.url)
+    .bind(ts(Utc::now()))
+    .bind(new.expires_at.map(ts))
+    .bind(new.max_visits)
+    .bind(new.redirect_type.map(i16::from))
+    .bind(new.owner_id)
+    .bind(new.domain_id)
+    .bind(&new.password_hash)
+    .bind(&new.title)
+    .bind(new.preview)
+    .fetch_all(executor)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(ref db_err) if db_err.code().as_deref() == Some(SQLITE_CONSTRAINT_UNIQUE) => {
+            CreateLinkError::CodeTaken(code)
+        }
+        err => anyhow::Error::new(err).context("Failed to create new link").into(),
+    })
+    .and_then(|links| returned(links).map_err(Into::into))
+}
+
+///
+/// Inserts links within a transaction. Unlike in Postgres, a taken code only fails its own statement, not the whole
+/// transaction, so no savepoints are needed to try another one.
+///
+struct LinkTransaction<'c>(Transaction<'c, Sqlite>);
+
+#[async_trait]
+impl LinkInserts for LinkTransaction<'_> {
+    async fn next_id(&mut self) -> anyhow::Result<i32> {
+        next_link_id(&mut self.0).await
+    }
+
+    async fn insert(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        insert_link(&mut self.0, link_id, code, new).await
+    }
+}
+
+#[async_trait]
+impl VisitStore for SqliteStore {
+    #[instrument(skip(self))]
+    async fn count_for_link(&self, link_id: i32) -> anyhow::Result<i64> {
+        query_scalar("Select count(*) From link_visits Where link_id = ?")
+            .bind(link_id)
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| format!("Failed to count link visits for {link_id}"))
+    }
+
+    #[instrument(skip_all, fields(visits = visits.len()))]
+    async fn insert_batch(&self, visits: &[(i32, NewVisit)]) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await.context("Failed to start transaction")?;
+        let mut inserted = 0;
+
+        for chunk in visits.chunks(VISITS_PER_INSERT) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "Insert Into link_visits (link_id, ts, referrer, user_agent, ip, accept_language, country, browser, os, device)
+                    Select * From (",
+            );
+            builder.push_values(chunk, |mut row, (link_id, visit)| {
+                row.push_bind(*link_id)
+                    .push_bind(ts(visit.ts))
+                    .push_bind(visit.referrer.clone())
+                    .push_bind(visit.user_agent.clone())
+                    .push_bind(visit.ip.clone())
+                    .push_bind(visit.accept_language.clone())
+                    .push_bind(visit.country.clone())
+                    .push_bind(visit.browser.clone())
+                    .push_bind(visit.os.clone())
+                    .push_bind(visit.device.clone());
+            });
+            // visits of links deleted in the meantime are skipped, they would fail the whole batch on the foreign key
+            builder.push(") As v Where Exists (Select 1 From links l Where l.link_id = v.column1)");
+            inserted += builder
+                .build()
+                .execute(&mut tx)
+                .await
+                .context("Failed to insert batch of visits")?
+                .rows_affected();
+        }
+
+        tx.commit().await.context("Failed to commit batch of visits")?;
+        Ok(inserted)
+    }
+
+    #[instrument(skip(self))]
+    async fn count_per_bucket(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: TimeBucket,
+    ) -> anyhow::Result<Vec<VisitsInBucket>> {
+        // SQLite has no `date_trunc()` for weeks, so the buckets are counted here
+        let timestamps: Vec<DateTime<Utc>> =
+            query_scalar("Select ts From link_visits Where link_id = ?1 And ts >= ?2 And ts < ?3")
+                .bind(link_id)
+                .bind(ts(from))
+                .bind(ts(to))
+                .fetch_all(&self.pool)
+                .await
+                .with_context(|| format!("Failed to count visits over time for {link_id}"))?;
+
+        Ok(bucket.count(from, to, timestamps))
+    }
+
+    #[instrument(skip(self))]
+    async fn count_by(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        dimension: VisitDimension,
+        limit: i64,
+    ) -> anyhow::Result<Vec<VisitsByKey>> {
+        let column = match dimension {
+            // grouped by host below, since SQLite has no regular expressions
+            VisitDimension::ReferrerHost => "referrer",
+            VisitDimension::Browser => "browser",
+            VisitDimension::Country => "country",
+        };
+        let rows: Vec<(Option<String>, i64)> = query_as(&format!(
+            "Select {column}, count(*) From link_visits Where link_id = ?1 And ts >= ?2 And ts < ?3 Group By 1"
+        ))
+        .bind(link_id)
+        .bind(ts(from))
+        .bind(ts(to))
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("Failed to count visits by {column} for {link_id}"))?;
+
+        let mut counts: HashMap<Option<String>, i64> = HashMap::new();
+        for (key, visits) in rows {
+            let key = match dimension {
+                VisitDimension::ReferrerHost => key.as_deref().and_then(referrer_host),
+                _ => key,
+            };
+            *counts.entry(key).or_default() += visits;
+        }
+        Ok(VisitsByKey::top(counts, limit))
+    }
+
+    fn export_visits(
+        &self,
+        scope: OwnerScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportStream<ExportedVisit> {
+        let pool = self.pool.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut rows = query_as(
+                r#"Select v.link_id, l.code, v.ts, v.referrer, v.user_agent, v.ip, v.accept_language,
+                        v.country, v.browser, v.os, v.device
+                    From link_visits v
+                    Join links l On l.link_id = v.link_id
+                    Where (?1 Is Null Or l.owner_id = ?1)
+                        And (?2 Is Null Or v.ts >= ?2)
+                        And (?3 Is Null Or v.ts < ?3)
+                    Order By v.ts"#,
+            )
+            .bind(scope.owner_id())
+            .bind(from.map(ts))
+            .bind(to.map(ts))
+            .fetch(&pool);
+            while let Some(visit) = rows.try_next().await.context("Failed to export visits")? {
+                yield visit;
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl DomainStore for SqliteStore {
+    #[instrument(skip(self))]
+    async fn list_domains(&self) -> anyhow::Result<Vec<Domain>> {
+        query_as("Select * From domains Order By domain_id")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list domains")
+    }
+
+    #[instrument(skip(self))]
+    async fn upsert_domain(&self, host: &str, fallback_url: Option<&str>) -> anyhow::Result<Domain> {
+        // not `Returning *`: the statement is only committed once it was stepped to its end, which `fetch_one` does not
+        // do - so the domain would be lost, when the `domain add` command exits right after
+        query(
+            r#"Insert Into domains (host, fallback_url, created_at) Values (?, ?, ?)
+                On Conflict (host) Do Update Set fallback_url = excluded.fallback_url"#,
+        )
+        .bind(host)
+        .bind(fallback_url)
+        .bind(ts(Utc::now()))
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to register domain {host}"))?;
+        query_as("Select * From domains Where host = ?")
+            .bind(host)
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| format!("Failed to register domain {host}"))
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for SqliteStore {
+    #[instrument(skip(self))]
+    async fn find_owner(&self, owner_id: i32) -> anyhow::Result<Option<Owner>> {
+        query_as("Select * From owners Where owner_id = ?")
+            .bind(owner_id)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("Error fetching owner {owner_id}"))
+    }
+
+    #[instrument(skip(self))]
+    async fn find_or_create_owner(&self, name: &str, is_admin: bool) -> anyhow::Result<Owner> {
+        query_as(
+            r#"Insert Into owners (name, is_admin, created_at) Values (?, ?, ?)
+                On Conflict (name) Do Update Set is_admin = owners.is_admin Or excluded.is_admin
+                Returning *"#,
+        )
+        .bind(name)
+        .bind(is_admin)
+        .bind(ts(Utc::now()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(returned)
+        .with_context(|| format!("Failed to create owner {name}"))
+    }
+
+    #[instrument(skip(self, key_hash))]
+    async fn insert_api_key(&self, owner_id: i32, name: &str, prefix: &str, key_hash: &str) -> anyhow::Result<ApiKey> {
+        query_as(&format!(
+            "Insert Into api_keys (owner_id, name, prefix, key_hash, created_at) Values (?, ?, ?, ?, ?)
+                Returning {API_KEY_COLUMNS}"
+        ))
+        .bind(owner_id)
+        .bind(name)
+        .bind(prefix)
+        .bind(key_hash)
+        .bind(ts(Utc::now()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(returned)
+        .context("Failed to create API key")
+    }
+
+    #[instrument(skip(self))]
+    async fn list_api_keys(&self) -> anyhow::Result<Vec<ApiKey>> {
+        query_as(&format!("Select {API_KEY_COLUMNS} From api_keys Order By api_key_id"))
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list API keys")
+    }
+
+    #[instrument(skip_all)]
+    async fn find_active_api_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>> {
+        query_as(&format!(
+            "Select {API_KEY_COLUMNS} From api_keys Where key_hash = ? And revoked_at Is Null"
+        ))
+        .bind(hash_key(key))
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to look up API key")
+    }
+
+    #[instrument(skip(self))]
+    async fn revoke_api_key(&self, api_key_id: i32) -> anyhow::Result<bool> {
+        query("Update api_keys Set revoked_at = ?2 Where api_key_id = ?1 And revoked_at Is Null")
+            .bind(api_key_id)
+            .bind(ts(Utc::now()))
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to revoke API key {api_key_id}"))
+            .map(|result| result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::stream::BoxStream;
+use tracing::{instrument, warn};
+
+use super::{
+    api_keys::{self, ApiKey},
+    domains::Domain,
+    link_visit::{ExportedVisit, NewVisit, TimeBucket, VisitDimension, VisitsByKey, VisitsInBucket},
+    links::{CreateLinkError, ExportedLink, Link, LinkChanges, NewLink},
+    owners::{Owner, OwnerScope},
+};
+use crate::codes::{CodeGenerator, CodeStrategy};
+
+/// How often to retry generating a code, before giving up
+const MAX_CODE_ATTEMPTS: usize = 5;
+
+/// Rows read one after the other (e.g. from a database cursor), instead of all at once
+pub type ExportStream<T> = BoxStream<'static, anyhow::Result<T>>;
+
+/// Where links, visits and API keys are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Storage {
+    /// The database at `--database-url`, Postgres or SQLite depending on its scheme
+    Database,
+    /// In memory only, everything is lost on restart - for demos and local development
+    Memory,
+}
+
+///
+/// Storage of [`Link`]s. Implemented for Postgres ([`super::postgres::PgStore`]), SQLite
+/// ([`super::sqlite::SqliteStore`]) and in memory ([`super::memory::MemoryStore`]).
+///
+#[async_trait]
+pub trait LinkStore: Send + Sync {
+    /// Looks up the code on the domain (`None` for the default domain)
+    async fn find_by_code(&self, scope: OwnerScope, domain_id: Option<i32>, code: &str)
+        -> anyhow::Result<Option<Link>>;
+
+    /// Reserves the id for a link inserted later on
+    async fn next_id(&self) -> anyhow::Result<i32>;
+
+    /// Inserts the link with the `code` (and the reserved `link_id`, if given)
+    async fn insert(&self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError>;
+
+    ///
+    /// Returns up to `limit` links ordered by their id, starting after the link with id `after`.
+    /// The id of the last returned link serves as cursor for the next page.
+    ///
+    async fn list(&self, scope: OwnerScope, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Link>>;
+
+    async fn update(&self, scope: OwnerScope, link_id: i32, changes: &LinkChanges) -> anyhow::Result<Option<Link>>;
+
+    ///
+    /// Deletes the link together with all its recorded visits.
+    /// Returns `false`, if there was no link with this id in the scope.
+    ///
+    async fn delete(&self, scope: OwnerScope, link_id: i32) -> anyhow::Result<bool>;
+
+    ///
+    /// Deletes all links (and their visits), which expired before `before`.
+    /// Links limited by visits count as expired at their last visit.
+    /// Returns the number of deleted links.
+    ///
+    async fn delete_expired(&self, before: DateTime<Utc>) -> anyhow::Result<u64>;
+
+    /// All links in the scope with their number of visits, ordered by id
+    fn export_links(&self, scope: OwnerScope) -> ExportStream<ExportedLink>;
+
+    ///
+    /// Creates all links like [`LinkStore::create`], but in a single transaction: Either all of them are created,
+    /// or none. The inner error names the index of the link which could not be created, the outer one is returned
+    /// if the transaction itself failed.
+    ///
+    async fn create_all(
+        &self,
+        generator: &CodeGenerator,
+        new: &[NewLink],
+    ) -> anyhow::Result<Result<Vec<Link>, (usize, CreateLinkError)>>;
+
+    ///
+    /// Creates a new link. If no code is given, one is generated by the `generator`.
+    /// Collisions, blocked words and reserved codes are retried transparently for generated codes, while a taken
+    /// user supplied code results in [`CreateLinkError::CodeTaken`].
+    ///
+    async fn create(&self, generator: &CodeGenerator, new: &NewLink) -> Result<Link, CreateLinkError> {
+        let mut store = self;
+        create_with(&mut store, generator, new).await
+    }
+}
+
+///
+/// The steps [`LinkStore::create`] is made of, implemented by each store as well as by whatever
+/// [`LinkStore::create_all`] inserts the links of its transaction with
+///
+#[async_trait]
+pub(super) trait LinkInserts: Send {
+    async fn next_id(&mut self) -> anyhow::Result<i32>;
+
+    async fn insert(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError>;
+}
+
+#[async_trait]
+impl<S: LinkStore + ?Sized> LinkInserts for &S {
+    async fn next_id(&mut self) -> anyhow::Result<i32> {
+        LinkStore::next_id(*self).await
+    }
+
+    async fn insert(&mut self, link_id: Option<i32>, code: String, new: &NewLink) -> Result<Link, CreateLinkError> {
+        LinkStore::insert(*self, link_id, code, new).await
+    }
+}
+
+/// Creates a link like described at [`LinkStore::create`]
+#[instrument(skip_all)]
+pub(super) async fn create_with(
+    inserts: &mut impl LinkInserts,
+    generator: &CodeGenerator,
+    new: &NewLink,
+) -> Result<Link, CreateLinkError> {
+    if let Some(ref code) = new.code {
+        return inserts.insert(None, code.clone(), new).await;
+    }
+
+    for attempt in 1..=MAX_CODE_ATTEMPTS {
+        let (link_id, code) = match generator.strategy() {
+            CodeStrategy::Random => (None, generator.random()),
+            CodeStrategy::Sequential => {
+                // reserve the id up front, since the code is derived from it
+                let link_id = inserts.next_id().await?;
+                (Some(link_id), generator.encode_id(link_id))
+            }
+        };
+        if generator.is_blocked(&code) {
+            warn!(attempt, code, "Generated code contains a blocked word, retrying");
+            continue;
+        }
+        if generator.is_reserved(&code) {
+            warn!(attempt, code, "Generated code is reserved, retrying");
+            continue;
+        }
+
+        match inserts.insert(link_id, code, new).await {
+            Err(CreateLinkError::CodeTaken(code)) => {
+                warn!(attempt, code, "Generated code is already taken, retrying");
+            }
+            res => return res,
+        }
+    }
+
+    Err(anyhow::anyhow!("Failed to generate an unused code after {MAX_CODE_ATTEMPTS} attempts").into())
+}
+
+/// Creates the links one after the other, stopping at the first one which fails
+pub(super) async fn create_each(
+    inserts: &mut impl LinkInserts,
+    generator: &CodeGenerator,
+    new: &[NewLink],
+) -> Result<Vec<Link>, (usize, CreateLinkError)> {
+    let mut links = Vec::with_capacity(new.len());
+    for (idx, new) in new.iter().enumerate() {
+        links.push(create_with(inserts, generator, new).await.map_err(|err| (idx, err))?);
+    }
+    Ok(links)
+}
+
+///
+/// Storage of the visits of links and the statistics derived from them
+///
+#[async_trait]
+pub trait VisitStore: Send + Sync {
+    async fn count_for_link(&self, link_id: i32) -> anyhow::Result<i64>;
+
+    /// Inserts many visits at once, cf. [`crate::visit_queue::VisitQueue`]
+    async fn insert_batch(&self, visits: &[(i32, NewVisit)]) -> anyhow::Result<u64>;
+
+    /// Counts the visits of a link in `[from, to)` per time bucket. Buckets without visits are included with 0.
+    async fn count_per_bucket(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: TimeBucket,
+    ) -> anyhow::Result<Vec<VisitsInBucket>>;
+
+    /// Counts the visits of a link in `[from, to)` grouped by the `dimension`, most frequent values first.
+    async fn count_by(
+        &self,
+        link_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        dimension: VisitDimension,
+        limit: i64,
+    ) -> anyhow::Result<Vec<VisitsByKey>>;
+
+    /// All visits of the links in the scope in `[from, to)` (unbounded if not given), ordered by time
+    fn export_visits(
+        &self,
+        scope: OwnerScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportStream<ExportedVisit>;
+}
+
+///
+/// Storage of the [`Domain`]s links can live on
+///
+#[async_trait]
+pub trait DomainStore: Send + Sync {
+    async fn list_domains(&self) -> anyhow::Result<Vec<Domain>>;
+
+    /// Registers the domain, or changes the fallback URL of an already registered one
+    async fn upsert_domain(&self, host: &str, fallback_url: Option<&str>) -> anyhow::Result<Domain>;
+}
+
+///
+/// Storage of [`Owner`]s and their [`ApiKey`]s
+///
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    async fn find_owner(&self, owner_id: i32) -> anyhow::Result<Option<Owner>>;
+
+    ///
+    /// Returns the owner with the given name and creates it, if it does not exist yet.
+    /// Passing `is_admin = true` promotes an existing owner to admin.
+    ///
+    async fn find_or_create_owner(&self, name: &str, is_admin: bool) -> anyhow::Result<Owner>;
+
+    /// Stores a key, of which only the visible `prefix` and the hash are known
+    async fn insert_api_key(&self, owner_id: i32, name: &str, prefix: &str, key_hash: &str) -> anyhow::Result<ApiKey>;
+
+    async fn list_api_keys(&self) -> anyhow::Result<Vec<ApiKey>>;
+
+    /// Looks up an active (not revoked) key by its plain value
+    async fn find_active_api_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>>;
+
+    /// Revokes the key, returns `false` if there was no active key with this id
+    async fn revoke_api_key(&self, api_key_id: i32) -> anyhow::Result<bool>;
+
+    ///
+    /// Creates a new random key for the owner and returns it together with the plain key.
+    ///
+    #[instrument(skip(self))]
+    async fn create_api_key(&self, owner_id: i32, name: &str) -> anyhow::Result<(ApiKey, String)> {
+        let key = api_keys::generate_key();
+        let api_key = self
+            .insert_api_key(
+                owner_id,
+                name,
+                api_keys::visible_prefix(&key),
+                &api_keys::hash_key(&key),
+            )
+            .await?;
+        Ok((api_key, key))
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use parquet::{
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    context::AppState,
+    db::{link_visit::ExportedVisit, links::ExportedLink, owners::OwnerScope, store::ExportStream},
+};
+
+/// Rows per Parquet row group, which have to be kept in memory until the group is written
+const PARQUET_ROW_GROUP_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    #[default]
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// Apache Parquet, only for the `export` command, since files are written in row groups
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Jsonl => "application/x-ndjson",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// What to export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportKind {
+    /// Links with their number of visits
+    #[default]
+    Links,
+    /// Single visits with the code of their link
+    Visits,
+}
+
+///
+/// Encodes the rows one by one as CSV or JSON Lines, so they can be streamed without loading all of them.
+///
+pub fn encode<T: ExportRow>(
+    rows: ExportStream<T>,
+    format: ExportFormat,
+) -> BoxStream<'static, anyhow::Result<Vec<u8>>> {
+    rows.enumerate()
+        .map(move |(idx, row)| match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    // the header is derived from the first row
+                    .has_headers(idx == 0)
+                    .from_writer(Vec::new());
+                writer.serialize(row?).context("Failed to encode CSV row")?;
+                writer.into_inner().context("Failed to encode CSV row")
+            }
+            ExportFormat::Jsonl => {
+                let mut line = serde_json::to_vec(&row?).context("Failed to encode JSON row")?;
+                line.push(b'\n');
+                Ok(line)
+            }
+            ExportFormat::Parquet => bail!("Parquet cannot be streamed"),
+        })
+        .boxed()
+}
+
+/// Value of a Parquet column, `None` is written as null
+pub enum ParquetValue {
+    Int32(Option<i32>),
+    Int64(Option<i64>),
+    Text(Option<String>),
+}
+
+impl ParquetValue {
+    fn timestamp(ts: Option<DateTime<Utc>>) -> Self {
+        Self::Int64(ts.map(|ts| ts.timestamp_micros()))
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(self, Self::Int32(None) | Self::Int64(None) | Self::Text(None))
+    }
+}
+
+/// A row, that can be exported in all formats
+pub trait ExportRow: Serialize + Send + 'static {
+    /// Schema of the Parquet file, the columns in the order of [`ExportRow::parquet_values`]
+    const PARQUET_SCHEMA: &'static str;
+
+    fn parquet_values(self) -> Vec<ParquetValue>;
+}
+
+impl ExportRow for ExportedLink {
+    const PARQUET_SCHEMA: &'static str = "
+        message link {
+            REQUIRED INT32 link_id;
+            REQUIRED BYTE_ARRAY code (UTF8);
+            OPTIONAL BYTE_ARRAY domain (UTF8);
+            REQUIRED BYTE_ARRAY url (UTF8);
+            REQUIRED INT64 created_at (TIMESTAMP(MICROS,true));
+            OPTIONAL INT64 expires_at (TIMESTAMP(MICROS,true));
+            OPTIONAL INT32 max_visits;
+            OPTIONAL INT32 redirect_type;
+            REQUIRED INT32 owner_id;
+            REQUIRED INT64 visits;
+        }";
+
+    fn parquet_values(self) -> Vec<ParquetValue> {
+        vec![
+            ParquetValue::Int32(Some(self.link_id)),
+            ParquetValue::Text(Some(self.code)),
+            ParquetValue::Text(self.domain),
+            ParquetValue::Text(Some(self.url)),
+            ParquetValue::timestamp(Some(self.created_at)),
+            ParquetValue::timestamp(self.expires_at),
+            ParquetValue::Int32(self.max_visits),
+            ParquetValue::Int32(self.redirect_type.map(i32::from)),
+            ParquetValue::Int32(Some(self.owner_id)),
+            ParquetValue::Int64(Some(self.visits)),
+        ]
+    }
+}
+
+impl ExportRow for ExportedVisit {
+    const PARQUET_SCHEMA: &'static str = "
+        message visit {
+            REQUIRED INT32 link_id;
+            REQUIRED BYTE_ARRAY code (UTF8);
+            REQUIRED INT64 ts (TIMESTAMP(MICROS,true));
+            OPTIONAL BYTE_ARRAY referrer (UTF8);
+            OPTIONAL BYTE_ARRAY user_agent (UTF8);
+            OPTIONAL BYTE_ARRAY ip (UTF8);
+            OPTIONAL BYTE_ARRAY accept_language (UTF8);
+            OPTIONAL BYTE_ARRAY country (UTF8);
+            OPTIONAL BYTE_ARRAY browser (UTF8);
+            OPTIONAL BYTE_ARRAY os (UTF8);
+            OPTIONAL BYTE_ARRAY device (UTF8);
+        }";
+
+    fn parquet_values(self) -> Vec<ParquetValue> {
+        vec![
+            ParquetValue::Int32(Some(self.link_id)),
+            ParquetValue::Text(Some(self.code)),
+            ParquetValue::timestamp(Some(self.ts)),
+            ParquetValue::Text(self.referrer),
+            ParquetValue::Text(self.user_agent),
+            ParquetValue::Text(self.ip),
+            ParquetValue::Text(self.accept_language),
+            ParquetValue::Text(self.country),
+            ParquetValue::Text(self.browser),
+            ParquetValue::Text(self.os),
+            ParquetValue::Text(self.device),
+        ]
+    }
+}
+
+///
+/// Writes the rows as Parquet file, in row groups of [`PARQUET_ROW_GROUP_SIZE`] rows.
+/// Returns the number of rows written.
+///
+async fn write_parquet<T: ExportRow>(mut rows: ExportStream<T>, out: impl Write + Send) -> anyhow::Result<u64> {
+    let schema = parse_message_type(T::PARQUET_SCHEMA).context("Invalid Parquet schema")?;
+    let mut writer = SerializedFileWriter::new(out, Arc::new(schema), Arc::new(WriterProperties::builder().build()))?;
+
+    let mut count = 0;
+    let mut group = Vec::with_capacity(PARQUET_ROW_GROUP_SIZE);
+    while let Some(row) = rows.try_next().await? {
+        group.push(row.parquet_values());
+        count += 1;
+        if group.len() == PARQUET_ROW_GROUP_SIZE {
+            write_row_group(&mut writer, &group)?;
+            group.clear();
+        }
+    }
+    if !group.is_empty() {
+        write_row_group(&mut writer, &group)?;
+    }
+
+    writer.close().context("Failed to finish Parquet file")?;
+    Ok(count)
+}
+
+fn write_row_group<W: Write + Send>(
+    writer: &mut SerializedFileWriter<W>,
+    rows: &[Vec<ParquetValue>],
+) -> anyhow::Result<()> {
+    let mut group = writer.next_row_group()?;
+    let mut column_idx = 0;
+    while let Some(mut column) = group.next_column()? {
+        let values = rows.iter().map(|row| &row[column_idx]);
+        // nulls are told by the definition level (ignored for required columns)
+        let def_levels: Vec<i16> = values.clone().map(|value| i16::from(!value.is_null())).collect();
+
+        match column.untyped() {
+            ColumnWriter::Int32ColumnWriter(ref mut writer) => {
+                let values: Vec<i32> = values
+                    .filter_map(|value| match value {
+                        ParquetValue::Int32(value) => *value,
+                        _ => None,
+                    })
+                    .collect();
+                writer.write_batch(&values, Some(&def_levels), None)?;
+            }
+            ColumnWriter::Int64ColumnWriter(ref mut writer) => {
+                let values: Vec<i64> = values
+                    .filter_map(|value| match value {
+                        ParquetValue::Int64(value) => *value,
+                        _ => None,
+                    })
+                    .collect();
+                writer.write_batch(&values, Some(&def_levels), None)?;
+            }
+            ColumnWriter::ByteArrayColumnWriter(ref mut writer) => {
+                let values: Vec<ByteArray> = values
+                    .filter_map(|value| match value {
+                        ParquetValue::Text(value) => value.as_deref().map(ByteArray::from),
+                        _ => None,
+                    })
+                    .collect();
+                writer.write_batch(&values, Some(&def_levels), None)?;
+            }
+            _ => bail!("Unsupported type of Parquet column {column_idx}"),
+        }
+
+        column.close()?;
+        column_idx += 1;
+    }
+    group.close()?;
+    Ok(())
+}
+
+///
+/// Exports all links or visits to a file (or stdout, if none is given).
+///
+#[instrument(skip(ctx))]
+pub async fn export(
+    ctx: &AppState,
+    kind: ExportKind,
+    format: Option<ExportFormat>,
+    output: Option<&PathBuf>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let format = format
+        .or_else(|| {
+            let extension = output?.extension()?.to_str()?;
+            ExportFormat::value_variants()
+                .iter()
+                .copied()
+                .find(|format| format.extension() == extension)
+        })
+        .unwrap_or_default();
+    let out: Box<dyn Write + Send> = match output {
+        Some(path) => Box::new(File::create(path).with_context(|| format!("Failed to create {}", path.display()))?),
+        None => Box::new(std::io::stdout()),
+    };
+    let out = BufWriter::new(out);
+
+    let count = match kind {
+        ExportKind::Links => write(ctx.links.export_links(OwnerScope::All), format, out).await?,
+        ExportKind::Visits => write(ctx.visits.export_visits(OwnerScope::All, from, to), format, out).await?,
+    };
+    info!(count, "Export finished");
+    Ok(())
+}
+
+async fn write<T: ExportRow>(
+    rows: ExportStream<T>,
+    format: ExportFormat,
+    mut out: impl Write + Send,
+) -> anyhow::Result<u64> {
+    if format == ExportFormat::Parquet {
+        return write_parquet(rows, out).await;
+    }
+
+    let mut count = 0;
+    let mut chunks = encode(rows, format);
+    while let Some(chunk) = chunks.try_next().await? {
+        out.write_all(&chunk).context("Failed to write export")?;
+        count += 1;
+    }
+    out.flush().context("Failed to write export")?;
+    Ok(count)
+}
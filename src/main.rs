@@ -1,16 +1,27 @@
 use clap::Parser;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    cli::{Args, CliCommand},
-    context::AppState,
+    cli::{ApiKeyCommand, Args, CliCommand, DomainCommand},
+    context::{AppState, Backend},
 };
 
+mod bulk;
+mod cache;
 mod cli;
+mod codes;
 mod context;
 mod db;
+mod export;
+mod password;
+mod qr;
+mod redirect;
 mod routes;
 mod telemetry;
+mod templates;
+mod validation;
+mod visit_queue;
+mod visitor;
 
 #[tokio::main]
 async fn main() {
@@ -21,6 +32,18 @@ async fn main() {
     telemetry::setup_tracing(&args).await;
     debug!("CMD args: {:#?}", args);
 
+    // does not need the database
+    if let CliCommand::Openapi = args.command {
+        match routes::openapi::spec().to_pretty_json() {
+            Ok(spec) => println!("{spec}"),
+            Err(err) => {
+                error!(err = ?err, "Failed to serialize OpenAPI document!");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
     info!("Init app context");
     let ctx = match AppState::new(args.clone()).await {
         Ok(ctx) => ctx,
@@ -33,7 +56,7 @@ async fn main() {
     match args.command {
         CliCommand::Migrate => {
             info!("Run database migrations");
-            match sqlx::migrate!().run(&ctx.pool).await {
+            match ctx.backend.migrate().await {
                 Ok(_) => info!("Migrations successfully applied!"),
                 Err(err) => {
                     error!(err = ?err, "Failed to apply migrations!");
@@ -41,7 +64,65 @@ async fn main() {
                 }
             }
         }
+        CliCommand::Cleanup { grace_period } => {
+            let before = chrono::Utc::now()
+                - chrono::Duration::from_std(grace_period.into()).expect("Grace period out of range");
+            info!(%before, "Delete links expired before");
+            match ctx.links.delete_expired(before).await {
+                Ok(deleted) => info!(deleted, "Expired links successfully deleted!"),
+                Err(err) => {
+                    error!(err = ?err, "Failed to delete expired links!");
+                    std::process::exit(2);
+                }
+            }
+        }
+        CliCommand::Openapi => unreachable!("handled before creating the app context"),
+        CliCommand::Import {
+            ref file,
+            format,
+            ref owner,
+            mode,
+        } => {
+            if let Err(err) = bulk::import(&ctx, file, format, owner, mode).await {
+                error!(err = ?err, "Import failed!");
+                std::process::exit(2);
+            }
+        }
+        CliCommand::Export {
+            kind,
+            format,
+            ref output,
+            from,
+            to,
+        } => {
+            if let Err(err) = export::export(&ctx, kind, format, output.as_ref(), from, to).await {
+                error!(err = ?err, "Export failed!");
+                std::process::exit(2);
+            }
+        }
+        CliCommand::ApiKey { ref command } => {
+            if let Err(err) = run_api_key_command(&ctx, command).await {
+                error!(err = ?err, "API key command failed!");
+                std::process::exit(2);
+            }
+        }
+        CliCommand::Domain { ref command } => {
+            if let Err(err) = run_domain_command(&ctx, command).await {
+                error!(err = ?err, "Domain command failed!");
+                std::process::exit(2);
+            }
+        }
         CliCommand::Serve { ref http_bind } => {
+            if let Backend::Memory = ctx.backend {
+                // nobody else can reach the in-memory storage to create a key, so hand one out
+                match ctx.api_keys.find_or_create_owner("demo", true).await {
+                    Ok(owner) => match ctx.api_keys.create_api_key(owner.owner_id, "demo").await {
+                        Ok((_, key)) => warn!(api_key = key, "Created admin API key for the in-memory storage"),
+                        Err(err) => error!(err = ?err, "Failed to create API key!"),
+                    },
+                    Err(err) => error!(err = ?err, "Failed to create owner!"),
+                }
+            }
             if let Err(err) = routes::serve(http_bind, ctx).await {
                 error!(err = ?err, "Failed to start HTTP server!");
                 std::process::exit(1);
@@ -49,3 +130,73 @@ async fn main() {
         }
     }
 }
+
+async fn run_api_key_command(ctx: &AppState, command: &ApiKeyCommand) -> anyhow::Result<()> {
+    if let Backend::Memory = ctx.backend {
+        anyhow::bail!("API keys of the in-memory storage only exist while the server runs");
+    }
+
+    match command {
+        ApiKeyCommand::Create { name, owner, admin } => {
+            let owner = ctx.api_keys.find_or_create_owner(owner, *admin).await?;
+            let (api_key, key) = ctx.api_keys.create_api_key(owner.owner_id, name).await?;
+            info!(
+                api_key_id = api_key.api_key_id,
+                "API key created, it won't be shown again!"
+            );
+            println!("{key}");
+        }
+        ApiKeyCommand::List => {
+            for api_key in ctx.api_keys.list_api_keys().await? {
+                let revoked = api_key.revoked_at.map(|ts| format!("revoked {ts}")).unwrap_or_default();
+                println!(
+                    "{}\t{}…\t{}\towner {}\tcreated {}\t{}",
+                    api_key.api_key_id, api_key.prefix, api_key.name, api_key.owner_id, api_key.created_at, revoked
+                );
+            }
+        }
+        ApiKeyCommand::Revoke { api_key_id } => {
+            if !ctx.api_keys.revoke_api_key(*api_key_id).await? {
+                anyhow::bail!("No active API key with id {api_key_id}");
+            }
+            info!(api_key_id, "API key revoked");
+        }
+    }
+    Ok(())
+}
+
+async fn run_domain_command(ctx: &AppState, command: &DomainCommand) -> anyhow::Result<()> {
+    if let Backend::Memory = ctx.backend {
+        anyhow::bail!("Domains of the in-memory storage only exist while the server runs");
+    }
+
+    match command {
+        DomainCommand::Add { host, fallback_url } => {
+            let Some(host) = validation::normalize_host(host) else {
+                anyhow::bail!("Invalid host name {host}");
+            };
+            let fallback_url = fallback_url
+                .as_deref()
+                .map(|url| validation::normalize_url(url, &ctx.args.allowed_url_schemes))
+                .transpose()
+                .map_err(|err| anyhow::anyhow!("Invalid fallback URL: {err}"))?;
+            let domain = ctx.domains.upsert_domain(&host, fallback_url.as_deref()).await?;
+            info!(domain_id = domain.domain_id, host = domain.host, "Domain registered");
+        }
+        DomainCommand::List => {
+            for domain in ctx.domains.list_domains().await? {
+                println!(
+                    "{}\t{}\tcreated {}\t{}",
+                    domain.domain_id,
+                    domain.host,
+                    domain.created_at,
+                    domain
+                        .fallback_url
+                        .map(|url| format!("fallback {url}"))
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
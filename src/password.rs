@@ -0,0 +1,28 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// Longer passwords are rejected, hashing them would just burn CPU time
+pub const MAX_PASSWORD_LENGTH: usize = 256;
+
+///
+/// Hashes the password of a link with argon2 and a random salt, in the PHC string format which
+/// also records the parameters used.
+///
+/// Takes a few milliseconds of CPU time on purpose, run it on a blocking thread when that matters.
+///
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow::anyhow!("Failed to hash password: {err}"))?;
+    Ok(hash.to_string())
+}
+
+/// Whether the password matches the hash from [`hash_password`], a malformed hash matches nothing
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
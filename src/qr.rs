@@ -0,0 +1,190 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use qrcode::{Color as Module, EcLevel, QrCode};
+use serde::{Deserialize, Deserializer};
+use utoipa::ToSchema;
+
+use crate::validation::InvalidField;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl QrFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// How much of the code may be damaged (or covered by a logo), while it can still be read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCorrection {
+    /// ~7%
+    L,
+    /// ~15%
+    #[default]
+    M,
+    /// ~25%
+    Q,
+    /// ~30%
+    H,
+}
+
+impl From<ErrorCorrection> for EcLevel {
+    fn from(value: ErrorCorrection) -> Self {
+        match value {
+            ErrorCorrection::L => Self::L,
+            ErrorCorrection::M => Self::M,
+            ErrorCorrection::Q => Self::Q,
+            ErrorCorrection::H => Self::H,
+        }
+    }
+}
+
+/// RGBA color, given in hex as `rrggbb` or `rrggbbaa` with an optional leading `#`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color([u8; 4]);
+
+impl Color {
+    pub const BLACK: Self = Self([0, 0, 0, 255]);
+    pub const WHITE: Self = Self([255, 255, 255, 255]);
+}
+
+impl FromStr for Color {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !matches!(hex.len(), 6 | 8) {
+            return Err("Color must be given as rrggbb or rrggbbaa");
+        }
+        let mut rgba = [255; 4];
+        hex::decode_to_slice(hex, &mut rgba[..hex.len() / 2]).map_err(|_| "Color must be hexadecimal")?;
+        Ok(Self(rgba))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", hex::encode(&self.0[..3]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// How the QR code is rendered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrOptions {
+    pub format: QrFormat,
+    /// Width and height of the image in pixels. PNGs are rounded down to a multiple of the modules
+    pub size: u32,
+    /// Width of the quiet zone around the code in modules
+    pub margin: u32,
+    pub error_correction: ErrorCorrection,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+///
+/// Renders `data` as QR code image.
+///
+pub fn render(data: &str, options: &QrOptions) -> Result<Vec<u8>, InvalidField> {
+    if options.foreground == options.background {
+        return Err(InvalidField::new("fg", "Foreground and background must differ"));
+    }
+    let code = QrCode::with_error_correction_level(data, options.error_correction.into())
+        .map_err(|err| InvalidField::new("ecc", format!("Cannot encode the URL: {err}")))?;
+
+    let modules = code.width() as u32 + 2 * options.margin;
+    match options.format {
+        QrFormat::Png => {
+            let scale = options.size / modules;
+            if scale == 0 {
+                return Err(InvalidField::new(
+                    "size",
+                    format!("Must be at least {modules} to fit the code"),
+                ));
+            }
+            render_png(&code, options, scale).map_err(|err| InvalidField::new("format", err))
+        }
+        QrFormat::Svg => Ok(render_svg(&code, options).into_bytes()),
+    }
+}
+
+/// Coordinates of the dark modules, the quiet zone included
+fn dark_modules<'a>(code: &'a QrCode, margin: u32) -> impl Iterator<Item = (u32, u32)> + 'a {
+    let width = code.width();
+    code.to_colors()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, module)| *module == Module::Dark)
+        .map(move |(idx, _)| ((idx % width) as u32 + margin, (idx / width) as u32 + margin))
+}
+
+fn render_png(code: &QrCode, options: &QrOptions, scale: u32) -> anyhow::Result<Vec<u8>> {
+    let modules = code.width() as u32 + 2 * options.margin;
+    let side = (modules * scale) as usize;
+
+    let mut pixels = options.background.0.repeat(side * side);
+    for (x, y) in dark_modules(code, options.margin) {
+        for row in (y * scale)..((y + 1) * scale) {
+            let start = (row as usize * side + (x * scale) as usize) * 4;
+            for pixel in pixels[start..start + scale as usize * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&options.foreground.0);
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("Failed to encode PNG")?;
+    writer.write_image_data(&pixels).context("Failed to encode PNG")?;
+    writer.finish().context("Failed to encode PNG")?;
+    Ok(png)
+}
+
+fn render_svg(code: &QrCode, options: &QrOptions) -> String {
+    let modules = code.width() as u32 + 2 * options.margin;
+    // one square per dark module, in the coordinates of the view box
+    let path: String = dark_modules(code, options.margin)
+        .map(|(x, y)| format!("M{x},{y}h1v1h-1z"))
+        .collect();
+
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" "#,
+            r#"viewBox="0 0 {modules} {modules}" shape-rendering="crispEdges">"#,
+            r#"<rect width="100%" height="100%" fill="{bg}" fill-opacity="{bg_opacity}"/>"#,
+            r#"<path d="{path}" fill="{fg}" fill-opacity="{fg_opacity}"/>"#,
+            "</svg>"
+        ),
+        size = options.size,
+        modules = modules,
+        bg = options.background,
+        bg_opacity = opacity(options.background),
+        path = path,
+        fg = options.foreground,
+        fg_opacity = opacity(options.foreground),
+    )
+}
+
+fn opacity(color: Color) -> String {
+    format!("{:.3}", f32::from(color.0[3]) / 255.0)
+}
@@ -0,0 +1,84 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    ToSchema,
+};
+
+///
+/// HTTP status code used to redirect visitors of a link.
+///
+/// Permanent redirects (301/308) let search engines pass rank to the target, but browsers cache them
+/// and won't ask us again. Temporary ones (302/307) make sure every visit gets counted.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum RedirectType {
+    #[value(name = "301")]
+    MovedPermanently,
+    #[value(name = "302")]
+    Found,
+    #[value(name = "307")]
+    TemporaryRedirect,
+    #[value(name = "308")]
+    PermanentRedirect,
+}
+
+impl RedirectType {
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            Self::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            Self::Found => StatusCode::FOUND,
+            Self::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+            Self::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+
+    pub fn redirect(self, url: &str) -> Response {
+        // `axum::response::Redirect` does not cover 301 and 302, so build the response by hand
+        (self.status_code(), [(header::LOCATION, url)]).into_response()
+    }
+}
+
+// by hand, because the derive does not know about the conversion to u16
+impl<'s> ToSchema<'s> for RedirectType {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::Integer)
+            .enum_values(Some([301, 302, 307, 308]))
+            .description(Some("HTTP status code used to redirect visitors of a link"));
+        ("RedirectType", schema.into())
+    }
+}
+
+impl From<RedirectType> for u16 {
+    fn from(value: RedirectType) -> Self {
+        value.status_code().as_u16()
+    }
+}
+
+impl From<RedirectType> for i16 {
+    fn from(value: RedirectType) -> Self {
+        u16::from(value) as i16
+    }
+}
+
+impl TryFrom<u16> for RedirectType {
+    type Error = String;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            301 => Ok(Self::MovedPermanently),
+            302 => Ok(Self::Found),
+            307 => Ok(Self::TemporaryRedirect),
+            308 => Ok(Self::PermanentRedirect),
+            other => Err(format!(
+                "unsupported redirect type {other}, use one of 301, 302, 307 or 308"
+            )),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, TypedHeader},
+    headers::{authorization::Bearer, Authorization},
+    http::request::Parts,
+};
+
+use super::errors::ApiError;
+use crate::{
+    context::AppState,
+    db::{api_keys::ApiKey, owners::Owner},
+};
+
+///
+/// Extractor ensuring the request carries a valid API key as `Authorization: Bearer <key>` header.
+/// Add it to the arguments of every handler, which must not be public.
+/// Use the scope of the owner to restrict, which links the request may touch.
+///
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub api_key: ApiKey,
+    pub owner: Owner,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Authenticated
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // already verified by a middleware, cf. `rate_limit::limit_creation`
+        if let Some(auth) = parts.extensions.get::<Self>() {
+            return Ok(auth.clone());
+        }
+        let ctx = AppState::from_ref(state);
+
+        let TypedHeader(Authorization(bearer)) = TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::Unauthorized("Missing API key."))?;
+
+        let api_key = ctx
+            .api_keys
+            .find_active_api_key(bearer.token())
+            .await?
+            .ok_or(ApiError::Unauthorized("Invalid API key."))?;
+        let owner = ctx
+            .api_keys
+            .find_owner(api_key.owner_id)
+            .await?
+            // the foreign key makes sure the owner exists
+            .ok_or(ApiError::Unauthorized("Invalid API key."))?;
+
+        Ok(Self { api_key, owner })
+    }
+}
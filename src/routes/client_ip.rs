@@ -0,0 +1,60 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::context::AppState;
+
+///
+/// Extractor for the IP address of the client.
+///
+/// If the request comes from one of the trusted proxies (cf. `Args::trusted_proxies`), the rightmost address
+/// in `X-Forwarded-For`, which is not a trusted proxy itself, is taken. Otherwise it's the peer address.
+/// Is `None`, if the server does not provide connection info (e.g. in tests).
+///
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = AppState::from_ref(state);
+        let trusted_proxies = &ctx.args.trusted_proxies;
+
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            return Ok(Self(None));
+        };
+        let peer = peer.ip();
+        if !trusted_proxies.contains(&peer) {
+            return Ok(Self(Some(peer)));
+        }
+
+        // each proxy appends the address it got the request from, so walk the chain from the right
+        let forwarded: Vec<IpAddr> = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|addr| addr.trim().parse().ok())
+            .collect();
+        let client = forwarded
+            .iter()
+            .rev()
+            .find(|addr| !trusted_proxies.contains(addr))
+            .or(forwarded.first())
+            .copied()
+            .unwrap_or(peer);
+
+        Ok(Self(Some(client)))
+    }
+}
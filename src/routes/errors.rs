@@ -1,14 +1,174 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::{Serialize, Serializer};
-use tracing::instrument;
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
 
-use crate::context::AppState;
+use crate::{context::AppState, db::links::CreateLinkError, validation::InvalidField};
 
-#[derive(Debug, Serialize)]
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Header carrying the id, which is reported with errors to correlate them with the logs and traces
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+///
+/// Everything a handler can fail with. Each variant maps to an HTTP status and a stable error code,
+/// which clients can match on instead of the human readable message.
+///
+/// Errors of the `db` layer convert into it, so handlers can just use `?`.
+///
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The named thing (e.g. `"Link"`) does not exist, or belongs to another owner
+    #[error("{0} not found.")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Gone(&'static str),
+    #[error("Invalid request.")]
+    Validation(Vec<FieldError>),
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("Too many requests, slow down.")]
+    RateLimited {
+        /// seconds until the client may try again
+        retry_after: u64,
+    },
+    /// Details are only logged, the client just gets a generic message
+    #[error("Internal server error.")]
+    Internal(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// name of the request field
+    #[schema(value_type = String)]
+    pub field: &'static str,
+    pub msg: String,
+}
+
+impl ApiError {
+    /// Error for a single request field, that did not pass validation
+    pub fn invalid_field(field: &'static str, msg: impl ToString) -> Self {
+        Self::Validation(vec![FieldError {
+            field,
+            msg: msg.to_string(),
+        }])
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Gone(_) => StatusCode::GONE,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable, machine-readable code of the error
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "not_found",
+            Self::Conflict(_) => "conflict",
+            Self::Gone(_) => "gone",
+            Self::Validation(_) => "validation_failed",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<CreateLinkError> for ApiError {
+    fn from(value: CreateLinkError) -> Self {
+        match value {
+            CreateLinkError::CodeTaken(code) => Self::Conflict(format!("Code '{code}' is already taken.")),
+            CreateLinkError::Other(err) => Self::Internal(err),
+        }
+    }
+}
+
+impl From<InvalidField> for ApiError {
+    fn from(value: InvalidField) -> Self {
+        Self::invalid_field(value.field, value.msg)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let Self::Internal(ref err) = self {
+            warn!(err = ?err, "Internal error while handling request!");
+        }
+
+        let (field, errors) = match self {
+            // a single field is also reported as `field`, like before there were several
+            Self::Validation(ref errors) if errors.len() == 1 => (Some(errors[0].field), errors.clone()),
+            Self::Validation(ref errors) => (None, errors.clone()),
+            _ => (None, Vec::new()),
+        };
+        let msg = match self {
+            Self::Validation(ref errors) if errors.len() == 1 => {
+                format!("Invalid {}: {}", errors[0].field, errors[0].msg)
+            }
+            _ => self.to_string(),
+        };
+        let body = ErrorMessage {
+            code: self.status(),
+            error: self.error_code(),
+            msg,
+            field,
+            errors,
+            request_id: None,
+        };
+
+        let mut res = body.clone().into_response();
+        match self {
+            Self::Unauthorized(_) => {
+                res.headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            Self::RateLimited { retry_after } => {
+                res.headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            }
+            _ => {}
+        }
+        // picked up by `error_middleware` to add the request id and negotiate the format
+        res.extensions_mut().insert(body);
+        res
+    }
+}
+
+/// Body of all error responses
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ErrorMessage {
+    /// HTTP status code
     #[serde(serialize_with = "serialize_status")]
+    #[schema(value_type = u16, example = 404)]
     code: StatusCode,
+    /// stable error code, e.g. `not_found` or `validation_failed`
+    #[schema(example = "not_found")]
+    error: &'static str,
     msg: String,
+    /// name of the request field, that caused the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    field: Option<&'static str>,
+    /// all fields, which did not pass validation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    /// id of the request, cf. the `X-Request-Id` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 // custom serializer, because `StatusCode` does not derive Serialize
@@ -16,33 +176,97 @@ fn serialize_status<S: Serializer>(value: &StatusCode, s: S) -> Result<S::Ok, S:
     s.serialize_u16(value.as_u16())
 }
 
-impl ErrorMessage {
-    pub fn new(code: StatusCode, msg: impl ToString) -> Self {
-        ErrorMessage {
-            code,
-            msg: msg.to_string(),
+impl IntoResponse for ErrorMessage {
+    fn into_response(self) -> Response {
+        (self.code, Json(self)).into_response()
+    }
+}
+
+///
+/// Error body as RFC 7807 "problem details", sent to clients accepting `application/problem+json`
+///
+#[derive(Debug, Serialize)]
+struct ProblemDetails {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    title: &'static str,
+    #[serde(serialize_with = "serialize_status")]
+    status: StatusCode,
+    detail: String,
+    // extension members
+    error: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl From<ErrorMessage> for ProblemDetails {
+    fn from(value: ErrorMessage) -> Self {
+        Self {
+            type_: "about:blank",
+            title: value.code.canonical_reason().unwrap_or("Error"),
+            status: value.code,
+            detail: value.msg,
+            error: value.error,
+            errors: value.errors,
+            request_id: value.request_id,
         }
     }
 }
 
-impl IntoResponse for ErrorMessage {
-    fn into_response(self) -> axum::response::Response {
-        let code = self.code;
-        let mut res = axum::Json(self).into_response();
-        *res.status_mut() = code;
-        res
+///
+/// Middleware tagging every response with a request id (taken from the `X-Request-Id` header,
+/// the trace id or generated), which is embedded into error bodies.
+/// Errors are rendered as `application/problem+json`, if the client prefers so.
+///
+pub async fn error_middleware<B>(req: Request<B>, next: Next<B>) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(ToString::to_string)
+        .or_else(axum_tracing_opentelemetry::find_current_trace_id)
+        .unwrap_or_else(|| nanoid::nanoid!());
+    let wants_problem = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/problem+json"));
+
+    let mut res = next.run(req).await;
+
+    if let Some(mut body) = res.extensions_mut().remove::<ErrorMessage>() {
+        body.request_id = Some(request_id.clone());
+        let (mut parts, _) = res.into_parts();
+        // drop the headers of the original body, keep the rest (e.g. `Retry-After`)
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = if wants_problem {
+            (
+                [(header::CONTENT_TYPE, "application/problem+json")],
+                Json(ProblemDetails::from(body)),
+            )
+                .into_response()
+        } else {
+            body.into_response()
+        };
+        res = (parts, body).into_response();
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
     }
+    res
 }
 
 #[instrument(skip_all)]
 pub async fn handle_404(State(_ctx): State<AppState>) -> impl IntoResponse {
-    ErrorMessage::new(StatusCode::NOT_FOUND, "Not found")
+    ApiError::NotFound("Resource")
 }
 
 #[instrument]
 pub async fn handle_error(err: std::io::Error) -> impl IntoResponse {
-    ErrorMessage::new(
-        StatusCode::INTERNAL_SERVER_ERROR,
-        format!("Internal server error: {}", err),
-    )
+    ApiError::Internal(err.into())
 }
@@ -0,0 +1,89 @@
+use axum::body::StreamBody;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Deserialize;
+use tracing::{instrument, warn};
+use utoipa::IntoParams;
+
+use super::auth::Authenticated;
+use super::errors::{ApiError, ApiResult};
+use super::extract::Query;
+use crate::context::AppState;
+use crate::export::{encode, ExportFormat, ExportKind};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct ExportQuery {
+    /// `csv` (default) or `jsonl`
+    #[serde(default)]
+    format: ExportFormat,
+    /// `links` (default) with their number of visits, or single `visits`
+    #[serde(default)]
+    kind: ExportKind,
+    /// only visits since then
+    from: Option<DateTime<Utc>>,
+    /// only visits before then
+    to: Option<DateTime<Utc>>,
+}
+
+///
+/// Exports the links of the owner (all links for admins) or their visits.
+/// The rows are streamed as they are read from the database, so exports of any size are fine.
+///
+#[utoipa::path(
+    get,
+    path = "/api/links/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "CSV or JSON Lines, depending on the format", content_type = "text/csv"),
+        (status = 401, body = ErrorMessage),
+        (status = 422, body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+pub(super) async fn export_links(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+    if query.format == ExportFormat::Parquet {
+        return Err(ApiError::invalid_field(
+            "format",
+            "Parquet is only supported by the `export` command",
+        ));
+    }
+
+    let scope = auth.owner.scope();
+    let chunks = match query.kind {
+        ExportKind::Links => encode(ctx.links.export_links(scope), query.format),
+        ExportKind::Visits => encode(ctx.visits.export_visits(scope, query.from, query.to), query.format),
+    };
+    // the status is sent already, so the client only notices errors by the aborted response
+    let chunks = chunks.map(|chunk| {
+        chunk.map_err(|err| {
+            warn!(err = ?err, "Export failed while streaming!");
+            Box::<dyn std::error::Error + Send + Sync>::from(err)
+        })
+    });
+
+    let filename = match query.kind {
+        ExportKind::Links => "links",
+        ExportKind::Visits => "visits",
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}.{}\"", query.format.extension()),
+            ),
+        ],
+        StreamBody::new(chunks),
+    )
+        .into_response())
+}
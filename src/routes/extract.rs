@@ -0,0 +1,115 @@
+//!
+//! Drop-in replacements for the extractors of axum, which reject malformed requests with an [`ApiError`] - so the
+//! client gets the same error body as for any other invalid request, instead of axum's plain text.
+//!
+
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{request::Parts, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use super::errors::ApiError;
+
+/// Like [`axum::Json`], which it also is as response
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+/// Like [`axum::extract::Query`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+/// Like [`axum::extract::Path`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+/// Like [`axum::Form`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Json<T>
+where
+    axum::Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::from_request(req, state)
+            .await
+            .map_err(|err| rejected("body", err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    axum::Json<T>: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Form<T>
+where
+    axum::Form<T>: FromRequest<S, B, Rejection = FormRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Form(value) = axum::Form::from_request(req, state)
+            .await
+            .map_err(|err| rejected("body", err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    axum::extract::Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map_err(|err| rejected("query", err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    axum::extract::Path<T>: FromRequestParts<S, Rejection = PathRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::from_request_parts(parts, state)
+            .await
+            .map_err(|err| rejected("path", err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// The request is invalid, unless the server is to blame (e.g. for a route without the path parameters expected)
+fn rejected(field: &'static str, status: StatusCode, msg: String) -> ApiError {
+    match status.is_server_error() {
+        true => ApiError::Internal(anyhow::anyhow!(msg)),
+        false => ApiError::invalid_field(field, msg),
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::client_ip::ClientIp;
+use super::errors::ApiResult;
+use super::extract::{Form, Path};
+use super::links::{followed_link, record_visit};
+use crate::codes::PREVIEW_SUFFIX;
+use crate::context::AppState;
+use crate::db::links::Link;
+use crate::password::verify_password;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub(super) struct ContinueForm {
+    /// required for protected links
+    password: Option<String>,
+}
+
+///
+/// Continues from the preview page or the password form, which are served instead of the redirect:
+/// Records the visit and redirects to the target, once the password (if any) is correct.
+/// Each link only gets a few password attempts per minute (cf. `--password-attempt-limit`), no matter who makes them.
+///
+#[utoipa::path(
+    post,
+    path = "/api/links/{code}",
+    params(("code" = String, Path, description = "Short code of the link")),
+    request_body(content = ContinueForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to the target"),
+        (status = 403, description = "Wrong password, the form is served again", content_type = "text/html"),
+        (status = 404, body = ErrorMessage),
+        (status = 410, description = "Link has expired", body = ErrorMessage),
+        (status = 429, description = "Too many attempts for the link or rate limit exceeded", content_type = "text/html"),
+    ),
+    tag = "links"
+)]
+#[instrument(skip(ctx, headers, form))]
+pub(super) async fn continue_link(
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Form(form): Form<ContinueForm>,
+) -> ApiResult<Response> {
+    // the forms post back to the URL they were served on, which might be the one of the preview
+    let code = code.strip_suffix(PREVIEW_SUFFIX).unwrap_or(&code);
+    let link = match followed_link(&ctx, &headers, code).await {
+        Ok(link) => link,
+        Err(res) => return Ok(res),
+    };
+
+    if let Some(ref password_hash) = link.password_hash {
+        if let Some(retry_after) = ctx.rate_limiters.password_attempt(link.link_id) {
+            let mut res = password_page(
+                &ctx,
+                StatusCode::TOO_MANY_REQUESTS,
+                &format!("Too many attempts, try again in {retry_after} seconds."),
+            );
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            return Ok(res);
+        }
+
+        // argon2 takes a while on purpose, which must not block other requests
+        let password = form.password.unwrap_or_default();
+        let password_hash = password_hash.clone();
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+            .await
+            .context("Failed to verify password")?;
+        if !valid {
+            metrics::increment_counter!("link_password_failures");
+            return Ok(password_page(&ctx, StatusCode::FORBIDDEN, "Wrong password, try again."));
+        }
+    }
+
+    record_visit(&ctx, &link, ip, &headers);
+    // always `303 See Other`: the browser has to follow with a GET, and must not cache it like a permanent redirect,
+    // which would skip the page next time
+    Ok(Redirect::to(&link.url).into_response())
+}
+
+/// Like `POST /api/links/{code}`, for the pages served at the short URL
+#[utoipa::path(
+    post,
+    path = "/{code}",
+    params(("code" = String, Path, description = "Short code of the link")),
+    request_body(content = ContinueForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to the target"),
+        (status = 403, description = "Wrong password, the form is served again", content_type = "text/html"),
+        (status = 404, body = ErrorMessage),
+        (status = 410, description = "Link has expired", body = ErrorMessage),
+        (status = 429, description = "Too many attempts for the link or rate limit exceeded", content_type = "text/html"),
+    ),
+    tag = "links"
+)]
+pub(super) async fn continue_short_link(
+    state: State<AppState>,
+    code: Path<String>,
+    client_ip: ClientIp,
+    headers: HeaderMap,
+    form: Form<ContinueForm>,
+) -> ApiResult<Response> {
+    continue_link(state, code, client_ip, headers, form).await
+}
+
+///
+/// Page asking for the password of a link, served instead of redirecting.
+/// The form posts back to the URL it was served on, which is handled by [`continue_link`].
+///
+pub(super) fn password_page(ctx: &AppState, status: StatusCode, error: &str) -> Response {
+    page(status, ctx.templates.password(error))
+}
+
+///
+/// Page showing where the link leads, served instead of redirecting.
+/// Its continue button posts back to the URL it was served on, which is handled by [`continue_link`].
+///
+pub(super) fn preview_page(ctx: &AppState, link: &Link) -> Response {
+    metrics::increment_counter!("link_previews_shown");
+    let host = url::Url::parse(&link.url)
+        .ok()
+        .and_then(|url| url.host_str().map(ToString::to_string))
+        .unwrap_or_default();
+    let title = link.title.as_deref().unwrap_or(&host);
+    page(
+        StatusCode::OK,
+        ctx.templates.preview(title, &link.url, &host, &link.code),
+    )
+}
+
+fn page(status: StatusCode, html: String) -> Response {
+    (
+        status,
+        [
+            // the pages must not be served from a cache, nor be framed by other sites to phish for the password
+            (header::CACHE_CONTROL, "no-store"),
+            (header::X_FRAME_OPTIONS, "DENY"),
+        ],
+        Html(html),
+    )
+        .into_response()
+}
@@ -1,109 +1,631 @@
-use axum::extract::Path;
-use axum::http::StatusCode;
-use axum::response::Redirect;
+use std::net::IpAddr;
+
+use axum::handler::Handler;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware;
+use axum::response::{Redirect, Response};
 use axum::routing::{get, post};
-use axum::{extract::State, response::IntoResponse, Json, Router};
+use axum::{extract::State, response::IntoResponse, Router};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tracing::{instrument, warn};
+use utoipa::{IntoParams, ToSchema};
 
-use super::errors::ErrorMessage;
+use super::auth::Authenticated;
+use super::client_ip::ClientIp;
+use super::errors::{ApiError, ApiResult};
+use super::export::export_links;
+use super::extract::{Json, Path, Query};
+use super::interstitial::{continue_link, continue_short_link, password_page, preview_page};
+use super::qr::get_link_qr;
+use super::rate_limit::{limit_creation, limit_redirects};
+use super::stats::get_link_stats;
+use crate::bulk::{self, BulkError, BulkMode};
+use crate::codes::PREVIEW_SUFFIX;
 use crate::context::AppState;
-use crate::db::link_visit::LinkVisit;
-use crate::db::links::Link;
+use crate::db::domains::{Domain, Domains};
+use crate::db::links::{Link, LinkChanges};
+use crate::db::owners::OwnerScope;
+use crate::redirect::RedirectType;
+use crate::validation::{normalize_host, normalize_url, CreateLinkPayload};
+use crate::visitor::visit_from_request;
 
-type ApiResult<T> = Result<T, ErrorMessage>;
+/// All routes but following a link (`GET` and `POST /:code`) and its QR code require an API key, cf. [`Authenticated`]
+pub fn router(ctx: &AppState) -> Router<AppState> {
+    let create_link = create_link.layer(middleware::from_fn_with_state(ctx.clone(), limit_creation));
+    let create_links_bulk = create_links_bulk.layer(middleware::from_fn_with_state(ctx.clone(), limit_creation));
+    let follow_link = follow_link.layer(middleware::from_fn_with_state(ctx.clone(), limit_redirects));
+    let continue_link = continue_link.layer(middleware::from_fn_with_state(ctx.clone(), limit_redirects));
+    // public like following links, so it shares their budget
+    let get_link_qr = get_link_qr.layer(middleware::from_fn_with_state(ctx.clone(), limit_redirects));
 
-pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/", post(create_link))
-        .route("/:code", get(follow_link))
+        .route("/", get(list_links).post(create_link))
+        .route("/bulk", post(create_links_bulk))
+        .route("/export", get(export_links))
+        .route(
+            "/:code",
+            get(follow_link)
+                .post(continue_link)
+                .patch(update_link)
+                .delete(delete_link),
+        )
         .route("/:code/meta", get(get_link_meta))
+        .route("/:code/stats", get(get_link_stats))
+        .route("/:code/qr", get(get_link_qr))
 }
 
-#[derive(Debug, Deserialize)]
-struct CreateLinkPayload {
-    url: String,
-    code: Option<String>,
+/// Short links at the root (`GET /:code`), shadowed by static routes like `/metrics`, cf. [`crate::codes::RESERVED_CODES`]
+pub fn short_link_router(ctx: &AppState) -> Router<AppState> {
+    let follow_short_link = follow_short_link.layer(middleware::from_fn_with_state(ctx.clone(), limit_redirects));
+    let continue_short_link = continue_short_link.layer(middleware::from_fn_with_state(ctx.clone(), limit_redirects));
+
+    Router::new().route("/:code", get(follow_short_link).post(continue_short_link))
+}
+
+/// Picks the namespace of the code in the path, since codes are unique per domain only
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct DomainQuery {
+    /// host of a registered domain, the default domain if not given
+    domain: Option<String>,
+}
+
+impl DomainQuery {
+    /// The domain named by the query, `None` for the default domain
+    pub(super) fn resolve<'a>(&self, domains: &'a Domains) -> ApiResult<Option<&'a Domain>> {
+        match self.domain {
+            Some(ref domain) => normalize_host(domain)
+                .and_then(|host| domains.by_host(&host))
+                .map(Some)
+                .ok_or(ApiError::NotFound("Domain")),
+            None => Ok(None),
+        }
+    }
 }
 
-#[instrument(skip(ctx))]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct ListLinksQuery {
+    /// id of the last link of the previous page
+    cursor: Option<i32>,
+    /// page size, capped by the server
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct ListLinksResponse {
+    links: Vec<Link>,
+    /// pass as `cursor` to fetch the next page; `None` if this is the last page
+    next_cursor: Option<i32>,
+}
+
+/// Links of the owner (all links for admins), oldest first
+#[utoipa::path(
+    get,
+    path = "/api/links",
+    params(ListLinksQuery),
+    responses(
+        (status = 200, body = ListLinksResponse),
+        (status = 401, body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+async fn list_links(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Query(query): Query<ListLinksQuery>,
+) -> ApiResult<Json<ListLinksResponse>> {
+    let limit = query
+        .limit
+        .unwrap_or(ctx.args.max_page_size)
+        .clamp(1, ctx.args.max_page_size);
+
+    let links = ctx.links.list(auth.owner.scope(), query.cursor, limit).await?;
+
+    let next_cursor = match links.last() {
+        Some(last) if links.len() as i64 == limit => Some(last.link_id),
+        _ => None,
+    };
+
+    Ok(Json(ListLinksResponse { links, next_cursor }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct CreatedLink {
+    #[serde(flatten)]
+    link: Link,
+    /// public URL to follow the link
+    short_url: String,
+}
+
+/// Creates a link, with a generated code unless one is given
+#[utoipa::path(
+    post,
+    path = "/api/links",
+    request_body = CreateLinkPayload,
+    responses(
+        (status = 200, body = CreatedLink),
+        (status = 401, body = ErrorMessage),
+        (status = 409, description = "Code is already taken", body = ErrorMessage),
+        (status = 422, body = ErrorMessage),
+        (status = 429, description = "Rate limit exceeded", body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx, headers), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
 async fn create_link(
+    auth: Authenticated,
     State(ctx): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateLinkPayload>,
-) -> ApiResult<impl IntoResponse> {
-    let link = Link::create(&ctx.pool, &payload.url, payload.code.as_ref())
-        .await
-        .map(Json)
-        .map_err(|err| {
-            warn!(err = ?err, "Something, something can't save link");
-            ErrorMessage::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create link.")
-        })?;
-
-    metrics::increment_counter!(
-        "links_created",
-        "with_code" => match payload.code {
-            Some(_) => "true",
-            None => "false",
+) -> ApiResult<Json<CreatedLink>> {
+    let domains = ctx.known_domains().await?;
+    let new_link = payload.validate(&ctx, auth.owner.owner_id, &domains).await?;
+    let link = bulk::create_link(&ctx, &new_link).await?;
+
+    Ok(Json(CreatedLink {
+        short_url: short_url(&ctx, &headers, domains.by_id(link.domain_id), &link.code),
+        link,
+    }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct BulkQuery {
+    /// whether to create the valid links only (`best_effort`), or nothing if any fails (`transactional`)
+    #[serde(default)]
+    mode: BulkMode,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct BulkCreateResponse {
+    created: usize,
+    failed: usize,
+    /// one per submitted link, in the same order
+    results: Vec<BulkItemResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(super) enum BulkItemResult {
+    Created {
+        link: Link,
+        short_url: String,
+    },
+    Failed {
+        /// stable error code, like the one of error responses
+        error: &'static str,
+        msg: String,
+        /// name of the field, that did not pass validation
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[schema(value_type = Option<String>)]
+        field: Option<&'static str>,
+    },
+    /// not created, because another link of a transactional request failed
+    Skipped,
+}
+
+impl BulkItemResult {
+    fn new(value: Result<Link, BulkError>, short_url: impl Fn(&Link) -> String) -> Self {
+        let err = match value {
+            Ok(link) => {
+                let short_url = short_url(&link);
+                return Self::Created { link, short_url };
+            }
+            Err(BulkError::Skipped) => return Self::Skipped,
+            Err(BulkError::Invalid(invalid)) => {
+                return Self::Failed {
+                    error: ApiError::from(invalid.clone()).error_code(),
+                    msg: invalid.to_string(),
+                    field: Some(invalid.field),
+                }
+            }
+            Err(BulkError::Create(err)) => ApiError::from(err),
+        };
+        if let ApiError::Internal(ref err) = err {
+            warn!(err = ?err, "Internal error while creating link of bulk!");
         }
-    );
+        Self::Failed {
+            error: err.error_code(),
+            msg: err.to_string(),
+            field: None,
+        }
+    }
+}
 
-    Ok(link)
+///
+/// Creates many links at once, e.g. from a spreadsheet. Each link is reported on its own,
+/// the request succeeds even if links fail. The whole request counts as one creation for the rate limit.
+///
+#[utoipa::path(
+    post,
+    path = "/api/links/bulk",
+    params(BulkQuery),
+    request_body = Vec<CreateLinkPayload>,
+    responses(
+        (status = 200, body = BulkCreateResponse),
+        (status = 401, body = ErrorMessage),
+        (status = 422, description = "Too many links", body = ErrorMessage),
+        (status = 429, description = "Rate limit exceeded", body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx, headers, payloads), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id, links = payloads.len()))]
+async fn create_links_bulk(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Query(query): Query<BulkQuery>,
+    headers: HeaderMap,
+    Json(payloads): Json<Vec<CreateLinkPayload>>,
+) -> ApiResult<Json<BulkCreateResponse>> {
+    if payloads.len() > ctx.args.max_bulk_links {
+        return Err(ApiError::invalid_field(
+            "links",
+            format!("at most {} links can be created at once", ctx.args.max_bulk_links),
+        ));
+    }
+
+    let domains = ctx.known_domains().await?;
+    let results = bulk::create_links(&ctx, auth.owner.owner_id, &domains, payloads, query.mode, |_, _| {}).await;
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    Ok(Json(BulkCreateResponse {
+        created: results.len() - failed,
+        failed,
+        results: results
+            .into_iter()
+            .map(|result| {
+                BulkItemResult::new(result, |link| {
+                    short_url(&ctx, &headers, domains.by_id(link.domain_id), &link.code)
+                })
+            })
+            .collect(),
+    }))
 }
 
-#[instrument(skip(ctx))]
-async fn follow_link(State(ctx): State<AppState>, Path(code): Path<String>) -> ApiResult<impl IntoResponse> {
-    let link = Link::find_by_code(&ctx.pool, &code)
+///
+/// Redirects to the target of the link and records the visit. Instead, protected links serve a form asking for
+/// their password, and links with `preview` (or any code followed by `+`) a page showing where they lead.
+/// Both continue with [`continue_link`], which records the visit then.
+/// The code is looked up on the domain named by the `Host` header, or on the default domain for any other host.
+///
+#[utoipa::path(
+    get,
+    path = "/api/links/{code}",
+    params(("code" = String, Path, description = "Short code of the link")),
+    responses(
+        (status = 307, description = "Redirect to the target, the status depends on the redirect type of the link"),
+        (status = 200, description = "Password form of a protected link, or the preview page", content_type = "text/html"),
+        (status = 404, description = "Unknown code, redirects to the fallback URL of the domain if it has one", body = ErrorMessage),
+        (status = 410, description = "Link has expired", body = ErrorMessage),
+        (status = 429, description = "Rate limit exceeded", body = ErrorMessage),
+    ),
+    tag = "links"
+)]
+#[instrument(skip(ctx, headers))]
+async fn follow_link(
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let (code, wants_preview) = match code.strip_suffix(PREVIEW_SUFFIX) {
+        Some(code) => (code, true),
+        None => (code.as_str(), false),
+    };
+    let link = match followed_link(&ctx, &headers, code).await {
+        Ok(link) => link,
+        Err(res) => return Ok(res),
+    };
+    // the visit is recorded, once the visitor continues
+    if link.password_hash.is_some() {
+        return Ok(password_page(&ctx, StatusCode::OK, ""));
+    }
+    if wants_preview || link.preview {
+        return Ok(preview_page(&ctx, &link));
+    }
+
+    record_visit(&ctx, &link, ip, &headers);
+    let redirect_type = link.redirect_type().unwrap_or(ctx.args.default_redirect_type);
+    Ok(redirect_type.redirect(&link.url))
+}
+
+///
+/// The link a visitor follows, looked up on the domain named by the `Host` header.
+/// Otherwise the response to send instead: the fallback redirect of the domain for unknown codes,
+/// the one for expired links, or the error.
+///
+pub(super) async fn followed_link(ctx: &AppState, headers: &HeaderMap, code: &str) -> Result<Link, Response> {
+    let domains = ctx
+        .known_domains()
         .await
-        .map_err(|err| {
-            warn!(err = ?err, "Error while fetching link by code!");
-            ErrorMessage::new(StatusCode::INTERNAL_SERVER_ERROR, "DB error")
-        })?
-        .ok_or_else(|| ErrorMessage::new(StatusCode::NOT_FOUND, "Link not found."))?;
-
-    // // spawn background task to mark visit
-    // let link_id = link.link_id;
-    // tokio::spawn(async move {
-    //     LinkVisit::mark_visit(&ctx.pool, link_id).await.ok();
-    // });
-
-    LinkVisit::mark_visit(&ctx.pool, link.link_id).await.ok();
+        .map_err(|err| ApiError::from(err).into_response())?;
+    let domain = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(normalize_host)
+        .and_then(|host| domains.by_host(&host));
+
+    let link = match resolve_link(ctx, domain.map(|domain| domain.domain_id), code).await {
+        Err(ApiError::NotFound(_)) => {
+            return Err(match domain.and_then(|domain| domain.fallback_url.as_deref()) {
+                Some(fallback_url) => Redirect::temporary(fallback_url).into_response(),
+                None => ApiError::NotFound("Link").into_response(),
+            });
+        }
+        result => result.map_err(IntoResponse::into_response)?,
+    };
+
+    match link.is_expired(ctx.visits.as_ref()).await {
+        Ok(false) => Ok(link),
+        Ok(true) => {
+            metrics::increment_counter!("links_expired_visited");
+            Err(match ctx.args.expired_link_fallback_url {
+                Some(ref fallback_url) => Redirect::temporary(fallback_url).into_response(),
+                None => ApiError::Gone("Link has expired.").into_response(),
+            })
+        }
+        Err(err) => Err(ApiError::from(err).into_response()),
+    }
+}
+
+/// Counts the visit of the link, right before redirecting to its target
+pub(super) fn record_visit(ctx: &AppState, link: &Link, ip: Option<IpAddr>, headers: &HeaderMap) {
+    // written in the background, so a slow database does not delay the redirect
+    ctx.visit_queue
+        .push(link.link_id, visit_from_request(headers, ip, &ctx.args));
     metrics::increment_counter!("links_visited");
+}
+
+/// Like `GET /api/links/{code}`, but with the short URL handed out on creation
+#[utoipa::path(
+    get,
+    path = "/{code}",
+    params(("code" = String, Path, description = "Short code of the link")),
+    responses(
+        (status = 307, description = "Redirect to the target, the status depends on the redirect type of the link"),
+        (status = 200, description = "Password form of a protected link, or the preview page", content_type = "text/html"),
+        (status = 404, body = ErrorMessage),
+        (status = 410, description = "Link has expired", body = ErrorMessage),
+        (status = 429, description = "Rate limit exceeded", body = ErrorMessage),
+    ),
+    tag = "links"
+)]
+async fn follow_short_link(
+    state: State<AppState>,
+    code: Path<String>,
+    client_ip: ClientIp,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    follow_link(state, code, client_ip, headers).await
+}
 
-    Ok(Redirect::temporary(&link.url))
+#[derive(Debug, Deserialize, ToSchema)]
+pub(super) struct UpdateLinkPayload {
+    url: Option<String>,
+    redirect_type: Option<RedirectType>,
 }
 
-#[derive(Debug, Serialize)]
-struct LinkMetaResponse {
+/// Changes the target or redirect type of a link
+#[utoipa::path(
+    patch,
+    path = "/api/links/{code}",
+    params(("code" = String, Path, description = "Short code of the link"), DomainQuery),
+    request_body = UpdateLinkPayload,
+    responses(
+        (status = 200, body = Link),
+        (status = 401, body = ErrorMessage),
+        (status = 404, body = ErrorMessage),
+        (status = 422, body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+async fn update_link(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    Query(domain): Query<DomainQuery>,
+    Json(payload): Json<UpdateLinkPayload>,
+) -> ApiResult<Json<Link>> {
+    let url = payload
+        .url
+        .map(|url| normalize_url(&url, &ctx.args.allowed_url_schemes))
+        .transpose()
+        .map_err(|err| ApiError::invalid_field("url", err))?;
+    let domains = ctx.known_domains().await?;
+    let domain = domain.resolve(&domains)?;
+    let link = find_link(&ctx, auth.owner.scope(), domain, &code).await?;
+
+    let changes = LinkChanges {
+        url,
+        redirect_type: payload.redirect_type,
+    };
+    let link = ctx
+        .links
+        .update(auth.owner.scope(), link.link_id, &changes)
+        .await?
+        // the link might have been deleted in the meantime
+        .ok_or(ApiError::NotFound("Link"))?;
+    ctx.link_cache.invalidate(link.domain_id, &link.code);
+
+    metrics::increment_counter!("links_updated");
+
+    Ok(Json(link))
+}
+
+/// Deletes a link and its visits
+#[utoipa::path(
+    delete,
+    path = "/api/links/{code}",
+    params(("code" = String, Path, description = "Short code of the link"), DomainQuery),
+    responses(
+        (status = 204, description = "Link deleted"),
+        (status = 401, body = ErrorMessage),
+        (status = 404, body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+async fn delete_link(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    Query(domain): Query<DomainQuery>,
+) -> ApiResult<StatusCode> {
+    let domains = ctx.known_domains().await?;
+    let link = find_link(&ctx, auth.owner.scope(), domain.resolve(&domains)?, &code).await?;
+
+    if !ctx.links.delete(auth.owner.scope(), link.link_id).await? {
+        return Err(ApiError::NotFound("Link"));
+    }
+    ctx.link_cache.invalidate(link.domain_id, &link.code);
+
+    metrics::increment_counter!("links_deleted");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+///
+/// Looks up a link by its code on the domain (`None` for the default domain) and maps a missing link
+/// (or one outside the scope) to a 404 response
+///
+pub(super) async fn find_link(
+    ctx: &AppState,
+    scope: OwnerScope,
+    domain: Option<&Domain>,
+    code: &str,
+) -> ApiResult<Link> {
+    ctx.links
+        .find_by_code(scope, domain.map(|domain| domain.domain_id), code)
+        .await?
+        .ok_or(ApiError::NotFound("Link"))
+}
+
+/// Like [`find_link`] for all owners, but consults the link cache first
+pub(super) async fn resolve_link(ctx: &AppState, domain_id: Option<i32>, code: &str) -> ApiResult<Link> {
+    let link = match ctx.link_cache.get(domain_id, code) {
+        Some(link) => link,
+        None => {
+            let link = ctx.links.find_by_code(OwnerScope::All, domain_id, code).await?;
+            ctx.link_cache.insert(domain_id, code, link.clone());
+            link
+        }
+    };
+    link.ok_or(ApiError::NotFound("Link"))
+}
+
+///
+/// Base of the short URLs without trailing slash: `--public-base-url` if configured, otherwise as seen by the
+/// client, taken from the `Host` header and `X-Forwarded-Proto` (defaulting to `http`).
+///
+pub(super) fn public_base_url(ctx: &AppState, headers: &HeaderMap) -> String {
+    if let Some(ref base_url) = ctx.args.public_base_url {
+        return base_url.as_str().trim_end_matches('/').to_string();
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = match headers.get("x-forwarded-proto").and_then(|proto| proto.to_str().ok()) {
+        Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+        _ => "http",
+    };
+    format!("{scheme}://{host}")
+}
+
+///
+/// The public URL to follow the link, cf. [`public_base_url`].
+/// Links of another domain are served by the same scheme on that domain.
+///
+pub(super) fn short_url(ctx: &AppState, headers: &HeaderMap, domain: Option<&Domain>, code: &str) -> String {
+    let base_url = public_base_url(ctx, headers);
+    match domain {
+        Some(domain) => {
+            let scheme = base_url.split_once("://").map_or("https", |(scheme, _)| scheme);
+            format!("{scheme}://{}/{code}", domain.host)
+        }
+        None => format!("{base_url}/{code}"),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct LinkMetaResponse {
     url: String,
     code: String,
+    /// host of the domain, `None` for the default domain
+    domain: Option<String>,
+    /// public URL to follow the link
+    short_url: String,
     visits: u64,
+    expires_at: Option<DateTime<Utc>>,
+    max_visits: Option<i32>,
+    /// `None`, if the link uses the server default
+    redirect_type: Option<RedirectType>,
+    /// visitors have to enter a password, before being redirected
+    password_protected: bool,
+    title: Option<String>,
+    /// visitors see a preview page with the target, before being redirected
+    preview: bool,
 }
 
 impl From<Link> for LinkMetaResponse {
     fn from(value: Link) -> Self {
         Self {
+            redirect_type: value.redirect_type(),
+            password_protected: value.password_hash.is_some(),
+            title: value.title,
+            preview: value.preview,
             url: value.url,
             code: value.code,
+            domain: None,
+            short_url: String::new(),
             visits: 0,
+            expires_at: value.expires_at,
+            max_visits: value.max_visits,
         }
     }
 }
 
-#[instrument(skip(ctx))]
-async fn get_link_meta(State(ctx): State<AppState>, Path(code): Path<String>) -> ApiResult<Json<LinkMetaResponse>> {
-    let link = match Link::find_by_code(&ctx.pool, &code).await {
-        Err(err) => {
-            warn!(err = ?err, "Error while fetching link by code!");
-            return Err(ErrorMessage::new(StatusCode::INTERNAL_SERVER_ERROR, "DB error"));
-        }
-        Ok(None) => return Err(ErrorMessage::new(StatusCode::NOT_FOUND, "Link not found.")),
-        Ok(Some(link)) => link,
-    };
-    let visit_count = LinkVisit::count_for_link_id(&ctx.pool, link.link_id)
-        .await
-        .unwrap_or_default();
+/// Details of a link, including its number of visits
+#[utoipa::path(
+    get,
+    path = "/api/links/{code}/meta",
+    params(("code" = String, Path, description = "Short code of the link"), DomainQuery),
+    responses(
+        (status = 200, body = LinkMetaResponse),
+        (status = 401, body = ErrorMessage),
+        (status = 404, body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx, headers), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+async fn get_link_meta(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    Query(domain): Query<DomainQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Json<LinkMetaResponse>> {
+    let domains = ctx.known_domains().await?;
+    let domain = domain.resolve(&domains)?;
+    // links of other owners are reported as not found, to not leak their existence
+    let link = find_link(&ctx, auth.owner.scope(), domain, &code).await?;
+    let visit_count = ctx.visits.count_for_link(link.link_id).await?;
 
+    let short_url = short_url(&ctx, &headers, domain, &link.code);
     let mut resp: LinkMetaResponse = link.into();
+    resp.domain = domain.map(|domain| domain.host.clone());
+    resp.short_url = short_url;
     resp.visits = visit_count as u64;
 
     Ok(Json(resp))
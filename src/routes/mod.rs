@@ -1,7 +1,21 @@
+mod auth;
+mod client_ip;
 mod errors;
+mod export;
+mod extract;
+mod interstitial;
 mod links;
-
-use std::{net::SocketAddr, time::Instant};
+pub mod openapi;
+mod qr;
+pub mod rate_limit;
+mod stats;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use axum::{
@@ -30,12 +44,28 @@ pub async fn serve(bind: &SocketAddr, ctx: AppState) -> anyhow::Result<()> {
             .layer(opentelemetry_tracing_layer()),
     );
 
-    let server = axum::Server::bind(bind).serve(app.into_make_service());
+    // periodically drop rate limit state of clients, which have not been seen for a while
+    let limiter_ctx = ctx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            limiter_ctx.rate_limiters.retain_recent();
+        }
+    });
+
+    // connect info is needed to determine the client IP
+    let server = axum::Server::bind(bind).serve(app.into_make_service_with_connect_info::<SocketAddr>());
     info!("Start http server on http://{}/", server.local_addr());
-    server
+    let result = server
         .with_graceful_shutdown(shutdown_signal())
         .await
-        .context("Error starting HTTP server!")
+        .context("Error starting HTTP server!");
+
+    // all requests are answered now, so no more visits are queued
+    ctx.visit_queue.shutdown().await;
+
+    result
 }
 
 fn http_router(ctx: AppState) -> Router {
@@ -50,8 +80,13 @@ fn http_router(ctx: AppState) -> Router {
                 std::future::ready(ctx.prom_handle.render())
             }),
         )
-        .nest("/api/links", links::router())
+        // short links at the root, after the static routes above
+        .merge(links::short_link_router(&ctx))
+        .nest("/api/links", links::router(&ctx))
+        .nest("/api", openapi::router(&ctx))
         .fallback(errors::handle_404)
+        // add request ids to error responses, after the fallback, so it is covered as well
+        .layer(middleware::from_fn(errors::error_middleware))
         // inject axum middleware to track request duration
         .route_layer(middleware::from_fn(metric_middleware))
         // make context available in handlers
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::Config;
+
+use super::{
+    errors::{ApiError, ApiResult, ErrorMessage, FieldError},
+    export,
+    extract::Path,
+    interstitial, links, qr, stats,
+};
+use crate::{
+    bulk::BulkMode,
+    context::AppState,
+    db::{
+        link_visit::{TimeBucket, VisitsByKey, VisitsInBucket},
+        links::Link,
+    },
+    export::{ExportFormat, ExportKind},
+    qr::{ErrorCorrection, QrFormat},
+    redirect::RedirectType,
+    validation::CreateLinkPayload,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        links::list_links,
+        links::create_link,
+        links::create_links_bulk,
+        links::follow_link,
+        links::follow_short_link,
+        interstitial::continue_link,
+        interstitial::continue_short_link,
+        links::update_link,
+        links::delete_link,
+        links::get_link_meta,
+        export::export_links,
+        qr::get_link_qr,
+        stats::get_link_stats,
+    ),
+    components(schemas(
+        Link,
+        RedirectType,
+        ErrorMessage,
+        FieldError,
+        CreateLinkPayload,
+        BulkMode,
+        ExportFormat,
+        ExportKind,
+        QrFormat,
+        ErrorCorrection,
+        links::CreatedLink,
+        links::BulkCreateResponse,
+        links::BulkItemResult,
+        links::UpdateLinkPayload,
+        links::ListLinksResponse,
+        links::LinkMetaResponse,
+        interstitial::ContinueForm,
+        stats::LinkStatsResponse,
+        TimeBucket,
+        VisitsInBucket,
+        VisitsByKey,
+    )),
+    modifiers(&ApiKeyAuth),
+    tags((name = "links", description = "Create, follow and manage short links"))
+)]
+struct ApiDoc;
+
+/// Registers the `Authorization: Bearer <API key>` scheme referenced by the paths
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("api_key", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        }
+    }
+}
+
+/// The OpenAPI 3 document of the HTTP API
+pub fn spec() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+///
+/// Serves the spec at `/openapi.json` and, if enabled, a Swagger UI page at `/docs`.
+/// The UI assets are bundled with the binary (by `utoipa-swagger-ui`), so the page works without access to a CDN.
+///
+pub fn router(ctx: &AppState) -> Router<AppState> {
+    let spec = spec();
+    let router = Router::new().route("/openapi.json", get(move || std::future::ready(Json(spec.clone()))));
+
+    if ctx.args.api_docs_ui {
+        router
+            .route("/docs", get(|| std::future::ready(Html(SWAGGER_UI_HTML))))
+            .route("/docs/:file", get(swagger_ui_asset))
+    } else {
+        router
+    }
+}
+
+/// Serves a file of the bundled Swagger UI, e.g. `swagger-ui-bundle.js`
+async fn swagger_ui_asset(Path(file): Path<String>) -> ApiResult<Response> {
+    // only needed for the initializer script, which the page replaces
+    let config = Arc::new(Config::from("/api/openapi.json"));
+    let asset = utoipa_swagger_ui::serve(&file, config)
+        .map_err(|err| anyhow!("Failed to serve Swagger UI asset: {err}"))?
+        .ok_or(ApiError::NotFound("File"))?;
+
+    Ok(([(header::CONTENT_TYPE, asset.content_type)], asset.bytes).into_response())
+}
+
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    <title>rust-axum-demo API</title>
+    <link rel="stylesheet" href="/api/docs/swagger-ui.css" />
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="/api/docs/swagger-ui-bundle.js"></script>
+    <script>
+        window.onload = () => {
+            window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+        };
+    </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,135 @@
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::instrument;
+use utoipa::IntoParams;
+
+use super::errors::{ApiError, ApiResult};
+use super::extract::{Path, Query};
+use super::links::{resolve_link, short_url, DomainQuery};
+use crate::context::AppState;
+use crate::qr::{render, Color, ErrorCorrection, QrFormat, QrOptions};
+
+/// Bounds of the image size in pixels, so nobody makes the server render huge bitmaps
+const SIZE_RANGE: std::ops::RangeInclusive<u32> = 64..=2048;
+const MAX_MARGIN: u32 = 32;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct QrQuery {
+    /// `png` (default) or `svg`
+    #[serde(default)]
+    format: QrFormat,
+    /// width and height in pixels, defaults to 256
+    size: Option<u32>,
+    /// quiet zone around the code in modules, defaults to 4
+    margin: Option<u32>,
+    /// error correction level `l`, `m` (default), `q` or `h`
+    #[serde(default)]
+    ecc: ErrorCorrection,
+    /// color of the modules as hex `rrggbb` or `rrggbbaa`, defaults to black
+    #[param(value_type = Option<String>)]
+    fg: Option<Color>,
+    /// background color as hex `rrggbb` or `rrggbbaa`, defaults to white
+    #[param(value_type = Option<String>)]
+    bg: Option<Color>,
+}
+
+impl QrQuery {
+    fn options(self) -> ApiResult<QrOptions> {
+        let size = self.size.unwrap_or(256);
+        if !SIZE_RANGE.contains(&size) {
+            return Err(ApiError::invalid_field(
+                "size",
+                format!("Must be between {} and {}", SIZE_RANGE.start(), SIZE_RANGE.end()),
+            ));
+        }
+        let margin = self.margin.unwrap_or(4);
+        if margin > MAX_MARGIN {
+            return Err(ApiError::invalid_field(
+                "margin",
+                format!("Must be at most {MAX_MARGIN}"),
+            ));
+        }
+
+        Ok(QrOptions {
+            format: self.format,
+            size,
+            margin,
+            error_correction: self.ecc,
+            foreground: self.fg.unwrap_or(Color::BLACK),
+            background: self.bg.unwrap_or(Color::WHITE),
+        })
+    }
+}
+
+///
+/// QR code encoding the short URL of the link.
+/// Responses carry an `ETag`, so clients can revalidate with `If-None-Match` instead of downloading it again.
+///
+#[utoipa::path(
+    get,
+    path = "/api/links/{code}/qr",
+    params(("code" = String, Path, description = "Short code of the link"), QrQuery, DomainQuery),
+    responses(
+        (status = 200, description = "PNG or SVG image, depending on the format", content_type = "image/png"),
+        (status = 304, description = "Image did not change since the given `If-None-Match`"),
+        (status = 404, body = ErrorMessage),
+        (status = 422, body = ErrorMessage),
+        (status = 429, description = "Rate limit exceeded", body = ErrorMessage),
+    ),
+    tag = "links"
+)]
+#[instrument(skip(ctx, headers))]
+pub(super) async fn get_link_qr(
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<QrQuery>,
+    Query(domain): Query<DomainQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let domains = ctx.known_domains().await?;
+    let domain = domain.resolve(&domains)?;
+    let link = resolve_link(&ctx, domain.map(|domain| domain.domain_id), &code).await?;
+    let url = short_url(&ctx, &headers, domain, &link.code);
+    let options = query.options()?;
+
+    // the image only depends on the URL and the options, so it need not be rendered to compare
+    let etag = format!(
+        "\"{}\"",
+        hex::encode(&Sha256::digest(format!("{url}\n{options:?}"))[..16])
+    );
+    let etag = HeaderValue::from_str(&etag).expect("ETag is a valid header value");
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=86400")),
+        // both are part of the encoded URL, unless the public base URL is configured
+        (header::VARY, HeaderValue::from_static("Host, X-Forwarded-Proto")),
+    ];
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let image = render(&url, &options)?;
+    metrics::increment_counter!("qr_codes_rendered");
+
+    Ok((
+        cache_headers,
+        [(header::CONTENT_TYPE, options.format.content_type())],
+        image,
+    )
+        .into_response())
+}
+
+/// Whether `If-None-Match` lists the ETag, weakly compared as RFC 9110 demands for it
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag).as_bytes() == etag.as_bytes())
+}
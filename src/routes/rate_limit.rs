@@ -0,0 +1,138 @@
+use std::{hash::Hash, num::NonZeroU32};
+
+use axum::{
+    extract::{FromRequestParts, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use governor::{
+    clock::{Clock, DefaultClock},
+    DefaultKeyedRateLimiter, Quota,
+};
+
+use super::{auth::Authenticated, client_ip::ClientIp, errors::ApiError};
+use crate::{cli::Args, context::AppState};
+
+///
+/// Per client budgets for expensive or abusable endpoints.
+/// A budget of `0` (cf. [`Args`]) disables the respective limit.
+///
+pub struct RateLimiters {
+    /// keyed by API key id
+    creation: Option<DefaultKeyedRateLimiter<i32>>,
+    creation_by_ip: Option<DefaultKeyedRateLimiter<String>>,
+    redirects: Option<DefaultKeyedRateLimiter<String>>,
+    /// keyed by link id
+    passwords: Option<DefaultKeyedRateLimiter<i32>>,
+}
+
+impl RateLimiters {
+    pub fn from_args(args: &Args) -> Self {
+        fn limiter<K: Clone + Hash + Eq>(per_minute: u32) -> Option<DefaultKeyedRateLimiter<K>> {
+            NonZeroU32::new(per_minute).map(|n| governor::RateLimiter::keyed(Quota::per_minute(n)))
+        }
+
+        Self {
+            creation: limiter(args.create_rate_limit),
+            creation_by_ip: limiter(args.create_ip_rate_limit),
+            redirects: limiter(args.redirect_rate_limit),
+            passwords: limiter(args.password_attempt_limit),
+        }
+    }
+
+    ///
+    /// Counts an attempt to enter the password of a link and returns the seconds to wait, once its budget is exhausted.
+    /// The budget belongs to the link rather than the client, so guessing from many addresses does not help either.
+    ///
+    pub fn password_attempt(&self, link_id: i32) -> Option<u64> {
+        retry_after(self.passwords.as_ref(), &link_id, "passwords")
+    }
+
+    /// Forget about clients, which did not show up for a while, to keep memory bounded
+    pub fn retain_recent(&self) {
+        fn retain<K: Clone + Hash + Eq>(limiter: &Option<DefaultKeyedRateLimiter<K>>) {
+            if let Some(limiter) = limiter {
+                limiter.retain_recent();
+                limiter.shrink_to_fit();
+            }
+        }
+
+        retain(&self.creation);
+        retain(&self.creation_by_ip);
+        retain(&self.redirects);
+        retain(&self.passwords);
+    }
+}
+
+///
+/// Middleware limiting link creation per client IP and per API key.
+/// The budget of the IP is charged first, so requests with made up keys count as well. The one of the key is only
+/// charged once the key is verified, which is then handed on to the [`Authenticated`] extractor of the handler.
+///
+pub async fn limit_creation<B>(
+    State(ctx): State<AppState>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+    if let Some(rejection) = check(ctx.rate_limiters.creation_by_ip.as_ref(), &ip, "creation_ip") {
+        return rejection;
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let auth = match Authenticated::from_request_parts(&mut parts, &ctx).await {
+        Ok(auth) => auth,
+        Err(err) => return err.into_response(),
+    };
+    if let Some(rejection) = check(
+        ctx.rate_limiters.creation.as_ref(),
+        &auth.api_key.api_key_id,
+        "creation",
+    ) {
+        return rejection;
+    }
+
+    parts.extensions.insert(auth);
+    next.run(Request::from_parts(parts, body)).await
+}
+
+///
+/// Middleware limiting redirects per client IP
+///
+pub async fn limit_redirects<B>(
+    State(ctx): State<AppState>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let key = ip.map(|ip| ip.to_string()).unwrap_or_default();
+
+    match check(ctx.rate_limiters.redirects.as_ref(), &key, "redirects") {
+        None => next.run(req).await,
+        Some(rejection) => rejection,
+    }
+}
+
+/// Returns the rejection, if the budget of the key is exhausted
+fn check<K>(limiter: Option<&DefaultKeyedRateLimiter<K>>, key: &K, limit: &'static str) -> Option<Response>
+where
+    K: Clone + Hash + Eq,
+{
+    let retry_after = retry_after(limiter, key, limit)?;
+    Some(ApiError::RateLimited { retry_after }.into_response())
+}
+
+/// Returns the seconds to wait, if the budget of the key is exhausted
+fn retry_after<K>(limiter: Option<&DefaultKeyedRateLimiter<K>>, key: &K, limit: &'static str) -> Option<u64>
+where
+    K: Clone + Hash + Eq,
+{
+    let not_until = limiter?.check_key(key).err()?;
+    metrics::increment_counter!("rate_limit_rejections", "limit" => limit);
+
+    let wait = not_until.wait_time_from(DefaultClock::default().now());
+    // round up, so clients do not retry too early
+    Some(wait.as_secs() + u64::from(wait.subsec_nanos() > 0))
+}
@@ -0,0 +1,104 @@
+use axum::extract::State;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use super::auth::Authenticated;
+use super::errors::{ApiError, ApiResult};
+use super::extract::{Json, Path, Query};
+use super::links::{find_link, DomainQuery};
+use crate::context::AppState;
+use crate::db::link_visit::{TimeBucket, VisitDimension, VisitsByKey, VisitsInBucket};
+
+/// Upper bound for the length of the timeline, so nobody asks for years in hourly buckets
+const MAX_BUCKETS: i64 = 1000;
+/// Number of entries in each breakdown
+const TOP_N: i64 = 10;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct StatsQuery {
+    /// defaults to 30 days before `to`
+    from: Option<DateTime<Utc>>,
+    /// defaults to now
+    to: Option<DateTime<Utc>>,
+    /// defaults to `day`
+    bucket: Option<TimeBucket>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct LinkStatsResponse {
+    code: String,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: TimeBucket,
+    total: i64,
+    /// visits per bucket, including empty ones
+    timeline: Vec<VisitsInBucket>,
+    /// most frequent referrer hosts
+    referrers: Vec<VisitsByKey>,
+    browsers: Vec<VisitsByKey>,
+    countries: Vec<VisitsByKey>,
+}
+
+/// Visits of a link over time, with the top referrers, browsers and countries
+#[utoipa::path(
+    get,
+    path = "/api/links/{code}/stats",
+    params(("code" = String, Path, description = "Short code of the link"), StatsQuery, DomainQuery),
+    responses(
+        (status = 200, body = LinkStatsResponse),
+        (status = 401, body = ErrorMessage),
+        (status = 404, body = ErrorMessage),
+        (status = 422, description = "Invalid time range", body = ErrorMessage),
+    ),
+    security(("api_key" = [])),
+    tag = "links"
+)]
+#[instrument(skip(auth, ctx), fields(api_key_id = auth.api_key.api_key_id, owner_id = auth.owner.owner_id))]
+pub(super) async fn get_link_stats(
+    auth: Authenticated,
+    State(ctx): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<StatsQuery>,
+    Query(domain): Query<DomainQuery>,
+) -> ApiResult<Json<LinkStatsResponse>> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - chrono::Duration::days(30));
+    let bucket = query.bucket.unwrap_or(TimeBucket::Day);
+
+    if from >= to {
+        return Err(ApiError::invalid_field("from", "must be before `to`"));
+    }
+    if (to - from).num_seconds() / bucket.duration().num_seconds() > MAX_BUCKETS {
+        return Err(ApiError::invalid_field(
+            "bucket",
+            format!("too many buckets, use a larger bucket or a shorter range (max {MAX_BUCKETS})"),
+        ));
+    }
+
+    let domains = ctx.known_domains().await?;
+    let link = find_link(&ctx, auth.owner.scope(), domain.resolve(&domains)?, &code).await?;
+    let link_id = link.link_id;
+
+    let (timeline, referrers, browsers, countries) = tokio::try_join!(
+        ctx.visits.count_per_bucket(link_id, from, to, bucket),
+        ctx.visits
+            .count_by(link_id, from, to, VisitDimension::ReferrerHost, TOP_N),
+        ctx.visits.count_by(link_id, from, to, VisitDimension::Browser, TOP_N),
+        ctx.visits.count_by(link_id, from, to, VisitDimension::Country, TOP_N),
+    )?;
+
+    Ok(Json(LinkStatsResponse {
+        code: link.code,
+        from,
+        to,
+        bucket,
+        total: timeline.iter().map(|bucket| bucket.visits).sum(),
+        timeline,
+        referrers,
+        browsers,
+        countries,
+    }))
+}
@@ -0,0 +1,88 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::{body_json, TestApp};
+
+#[sqlx::test]
+async fn bulk_creates_valid_links_and_reports_failures(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "taken" }))
+        .await;
+
+    let payload = json!([
+        { "url": "https://example.com/a", "code": "a" },
+        { "url": "ftp://example.com" },
+        { "url": "https://example.com/b", "code": "taken" },
+        { "url": "https://example.com/c" },
+    ]);
+    let res = app
+        .send(Method::POST, "/api/links/bulk", Some(&key), Some(payload))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert_eq!(body["created"], 2);
+    assert_eq!(body["failed"], 2);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results[0]["status"], "created");
+    assert_eq!(results[0]["link"]["code"], "a");
+    assert_eq!(results[1]["status"], "failed");
+    assert_eq!(results[1]["error"], "validation_failed");
+    assert_eq!(results[1]["field"], "url");
+    assert_eq!(results[2]["error"], "conflict");
+    assert_eq!(results[3]["status"], "created");
+    assert_eq!(
+        app.get("/api/links/a", None).await.status(),
+        StatusCode::TEMPORARY_REDIRECT
+    );
+}
+
+#[sqlx::test]
+async fn transactional_bulk_creates_nothing_on_failure(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "taken" }))
+        .await;
+
+    let payload = json!([
+        { "url": "https://example.com/a", "code": "a" },
+        { "url": "https://example.com/b", "code": "taken" },
+        { "url": "https://example.com/c", "code": "c" },
+    ]);
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links/bulk?mode=transactional",
+            Some(&key),
+            Some(payload),
+        )
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert_eq!(body["created"], 0);
+    let statuses: Vec<_> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["skipped", "failed", "skipped"]);
+    assert_eq!(app.get("/api/links/a", None).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(app.get("/api/links/c", None).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn bulk_rejects_too_many_links(pool: PgPool) {
+    let app = TestApp::with_args(pool, &["--max-bulk-links", "1"]);
+    let key = app.api_key("alice", false).await;
+
+    let payload = json!([{ "url": "https://example.com/a" }, { "url": "https://example.com/b" }]);
+    let res = app
+        .send(Method::POST, "/api/links/bulk", Some(&key), Some(payload))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
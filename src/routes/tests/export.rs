@@ -0,0 +1,56 @@
+use axum::http::{header, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::TestApp;
+
+#[sqlx::test]
+async fn export_links_as_csv(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(
+        &key,
+        json!({ "url": "https://example.com/a", "code": "a", "max_visits": 5 }),
+    )
+    .await;
+    app.create_link(&key, json!({ "url": "https://example.com/b", "code": "b" }))
+        .await;
+    app.get("/api/links/a", None).await;
+    app.flush_visits().await;
+
+    let res = app.get("/api/links/export", Some(&key)).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/csv");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let mut rows = csv::Reader::from_reader(body.as_ref());
+    assert_eq!(
+        rows.headers().unwrap(),
+        vec![
+            "link_id",
+            "code",
+            "domain",
+            "url",
+            "created_at",
+            "expires_at",
+            "max_visits",
+            "redirect_type",
+            "owner_id",
+            "visits"
+        ]
+    );
+    let rows: Vec<_> = rows.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!((&rows[0][1], &rows[0][6], &rows[0][9]), ("a", "5", "1"));
+    assert_eq!((&rows[1][1], &rows[1][6], &rows[1][9]), ("b", "", "0"));
+}
+
+#[sqlx::test]
+async fn export_rejects_parquet(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let res = app.get("/api/links/export?format=parquet", Some(&key)).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
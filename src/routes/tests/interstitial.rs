@@ -0,0 +1,164 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::{body_json, TestApp};
+
+async fn post_form(app: &TestApp, uri: &str, form: &str) -> Response {
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form.to_string()))
+        .unwrap();
+    app.request(req).await
+}
+
+#[sqlx::test]
+async fn protected_link_asks_for_password(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    let link = app
+        .create_link(
+            &key,
+            json!({ "url": "https://example.com/internal", "code": "secret", "password": "hunter2" }),
+        )
+        .await;
+    assert!(link.get("password").is_none() && link.get("password_hash").is_none());
+
+    let res = app.get("/secret", None).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert!(std::str::from_utf8(&body).unwrap().contains(r#"type="password""#));
+
+    let res = post_form(&app, "/secret", "password=hunter3").await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = post_form(&app, "/secret", "password=hunter2").await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/internal");
+    app.flush_visits().await;
+
+    // only the successful attempt counts as visit
+    let meta = body_json(app.get("/api/links/secret/meta", Some(&key)).await).await;
+    assert_eq!(meta["password_protected"], true);
+    assert_eq!(meta["visits"], 1);
+    assert!(meta.get("password_hash").is_none());
+}
+
+#[sqlx::test]
+async fn password_attempts_are_limited_per_link(pool: PgPool) {
+    let app = TestApp::with_args(pool, &["--password-attempt-limit", "2"]);
+    let key = app.api_key("alice", false).await;
+    for code in ["one", "two"] {
+        app.create_link(
+            &key,
+            json!({ "url": "https://example.com", "code": code, "password": "hunter2" }),
+        )
+        .await;
+    }
+
+    for _ in 0..2 {
+        assert_eq!(
+            post_form(&app, "/one", "password=guess").await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+    // even the right password is rejected, until the budget of the link recovers
+    let res = post_form(&app, "/one", "password=hunter2").await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(res.headers().contains_key(header::RETRY_AFTER));
+
+    let res = post_form(&app, "/two", "password=hunter2").await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+}
+
+#[sqlx::test]
+async fn empty_password_is_rejected(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links",
+            Some(&key),
+            Some(json!({ "url": "https://example.com", "password": "" })),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body_json(res).await["field"], "password");
+}
+
+#[sqlx::test]
+async fn preview_shows_target_and_counts_visit_on_continue(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(
+        &key,
+        json!({ "url": "https://example.com/docs?a=1&b=2", "code": "docs", "title": "<Docs & more>" }),
+    )
+    .await;
+
+    let res = app.get("/docs+", None).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let page = std::str::from_utf8(&body).unwrap();
+    assert!(page.contains("&lt;Docs &amp; more&gt;"), "{page}");
+    assert!(page.contains("https://example.com/docs?a=1&amp;b=2"), "{page}");
+
+    // the form posts back to the URL of the preview
+    let res = post_form(&app, "/docs+", "").await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/docs?a=1&b=2");
+    // without the suffix, it still redirects right away
+    let res = app.get("/docs", None).await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    app.flush_visits().await;
+
+    // the preview itself is no visit
+    let meta = body_json(app.get("/api/links/docs/meta", Some(&key)).await).await;
+    assert_eq!(meta["visits"], 2);
+    assert_eq!(meta["preview"], false);
+}
+
+#[sqlx::test]
+async fn link_with_preview_always_shows_it(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(
+        &key,
+        json!({ "url": "https://example.com", "code": "ext", "preview": true }),
+    )
+    .await;
+
+    let res = app.get("/ext", None).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+    let res = post_form(&app, "/ext", "").await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+}
+
+#[sqlx::test]
+async fn preview_template_can_be_replaced(pool: PgPool) {
+    let dir = std::env::temp_dir().join(format!("templates-{}", nanoid::nanoid!()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("preview.html"), "Brand: {{ title }} -> {{url}} {{unknown}}").unwrap();
+    let app = TestApp::with_args(pool, &["--templates-dir", dir.to_str().unwrap()]);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "brand" }))
+        .await;
+
+    let res = app.get("/brand+", None).await;
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        "Brand: example.com -> https://example.com/ {{unknown}}"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
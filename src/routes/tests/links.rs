@@ -0,0 +1,292 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::{body_json, TestApp};
+
+#[sqlx::test]
+async fn create_link_generates_code(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let link = app.create_link(&key, json!({ "url": "https://example.com" })).await;
+
+    assert_eq!(link["url"], "https://example.com/");
+    assert_eq!(link["code"].as_str().unwrap().len(), 7);
+}
+
+#[sqlx::test]
+async fn create_link_with_taken_code_conflicts(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    let payload = json!({ "url": "https://example.com", "code": "taken" });
+
+    let link = app.create_link(&key, payload.clone()).await;
+    assert_eq!(link["code"], "taken");
+
+    let res = app.send(Method::POST, "/api/links", Some(&key), Some(payload)).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert_eq!(body_json(res).await["error"], "conflict");
+}
+
+#[sqlx::test]
+async fn create_link_rejects_invalid_url(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links",
+            Some(&key),
+            Some(json!({ "url": "ftp://example.com" })),
+        )
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_json(res).await;
+    assert_eq!(body["error"], "validation_failed");
+    assert_eq!(body["field"], "url");
+}
+
+#[sqlx::test]
+async fn create_link_requires_api_key(pool: PgPool) {
+    let app = TestApp::new(pool);
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links",
+            None,
+            Some(json!({ "url": "https://example.com" })),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links",
+            Some("axd_invalid"),
+            Some(json!({ "url": "https://example.com" })),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn malformed_json_body_is_a_validation_error(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri("/api/links")
+        .header(header::AUTHORIZATION, format!("Bearer {key}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{ "url": "#))
+        .unwrap();
+    let res = app.request(req).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_json(res).await;
+    assert_eq!(body["error"], "validation_failed");
+    assert_eq!(body["field"], "body");
+    assert!(body["request_id"].is_string());
+}
+
+#[sqlx::test]
+async fn malformed_query_is_a_validation_error(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "abc" }))
+        .await;
+
+    let req = Request::builder()
+        .uri("/api/links/abc/qr?size=abc")
+        .header(header::ACCEPT, "application/problem+json")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.request(req).await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/problem+json");
+    let body = body_json(res).await;
+    assert_eq!(body["error"], "validation_failed");
+    assert_eq!(body["errors"][0]["field"], "query");
+    assert!(body["request_id"].is_string());
+}
+
+#[sqlx::test]
+async fn creation_is_limited_per_api_key(pool: PgPool) {
+    let app = TestApp::with_args(pool, &["--create-rate-limit", "1"]);
+    let alice = app.api_key("alice", false).await;
+    let bob = app.api_key("bob", false).await;
+    let payload = json!({ "url": "https://example.com" });
+
+    app.create_link(&alice, payload.clone()).await;
+    let res = app
+        .send(Method::POST, "/api/links", Some(&alice), Some(payload.clone()))
+        .await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(res.headers().contains_key(header::RETRY_AFTER));
+
+    app.create_link(&bob, payload).await;
+}
+
+#[sqlx::test]
+async fn creation_with_made_up_keys_is_limited_per_ip(pool: PgPool) {
+    let app = TestApp::with_args(pool, &["--create-ip-rate-limit", "2"]);
+    let key = app.api_key("alice", false).await;
+    let payload = json!({ "url": "https://example.com" });
+
+    // every made up key still charges the budget of the client
+    for invalid in ["axd_one", "axd_two"] {
+        let res = app
+            .send(Method::POST, "/api/links", Some(invalid), Some(payload.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+    let res = app.send(Method::POST, "/api/links", Some(&key), Some(payload)).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[sqlx::test]
+async fn follow_link_redirects(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com/target", "code": "go" }))
+        .await;
+
+    let res = app.get("/api/links/go", None).await;
+
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/target");
+}
+
+#[sqlx::test]
+async fn short_url_redirects_at_the_root(pool: PgPool) {
+    let app = TestApp::with_args(pool, &["--public-base-url", "https://sho.rt/"]);
+    let key = app.api_key("alice", false).await;
+
+    let link = app
+        .create_link(&key, json!({ "url": "https://example.com/target", "code": "go" }))
+        .await;
+    assert_eq!(link["short_url"], "https://sho.rt/go");
+    let meta = body_json(app.get("/api/links/go/meta", Some(&key)).await).await;
+    assert_eq!(meta["short_url"], "https://sho.rt/go");
+
+    let res = app.get("/go", None).await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/target");
+
+    // static routes at the root are not shadowed
+    assert_eq!(app.get("/robots.txt", None).await.status(), StatusCode::OK);
+    assert_eq!(app.get("/unknown", None).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn reserved_codes_are_rejected(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    for code in ["metrics", "robots.txt", "Export"] {
+        let res = app
+            .send(
+                Method::POST,
+                "/api/links",
+                Some(&key),
+                Some(json!({ "url": "https://example.com", "code": code })),
+            )
+            .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{code}");
+    }
+}
+
+#[sqlx::test]
+async fn follow_link_uses_redirect_type_of_link(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(
+        &key,
+        json!({ "url": "https://example.com", "code": "perm", "redirect_type": 301 }),
+    )
+    .await;
+
+    let res = app.get("/api/links/perm", None).await;
+
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+}
+
+#[sqlx::test]
+async fn unknown_code_is_not_found(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+
+    let res = app.get("/api/links/missing", None).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(body_json(res).await["error"], "not_found");
+
+    let res = app.get("/api/links/missing/meta", Some(&key)).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn expired_link_is_gone(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(
+        &key,
+        json!({ "url": "https://example.com", "code": "once", "max_visits": 1 }),
+    )
+    .await;
+
+    assert_eq!(
+        app.get("/api/links/once", None).await.status(),
+        StatusCode::TEMPORARY_REDIRECT
+    );
+    app.flush_visits().await;
+
+    assert_eq!(app.get("/api/links/once", None).await.status(), StatusCode::GONE);
+}
+
+#[sqlx::test]
+async fn meta_counts_visits(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "meta" }))
+        .await;
+
+    for _ in 0..3 {
+        app.get("/api/links/meta", None).await;
+    }
+    app.flush_visits().await;
+
+    let res = app.get("/api/links/meta/meta", Some(&key)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let meta = body_json(res).await;
+    assert_eq!(meta["url"], "https://example.com/");
+    assert_eq!(meta["visits"], 3);
+}
+
+#[sqlx::test]
+async fn meta_of_other_owner_is_not_found(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let alice = app.api_key("alice", false).await;
+    let bob = app.api_key("bob", false).await;
+    let admin = app.api_key("admin", true).await;
+    app.create_link(&alice, json!({ "url": "https://example.com", "code": "alice" }))
+        .await;
+
+    assert_eq!(
+        app.get("/api/links/alice/meta", Some(&bob)).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        app.get("/api/links/alice/meta", Some(&admin)).await.status(),
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,131 @@
+//!
+//! Harness for driving the HTTP router in tests.
+//!
+//! Tests use `#[sqlx::test]`, which creates a fresh, migrated database for each test. It needs `DATABASE_URL`
+//! to point to a Postgres server, where the user may create databases (e.g. the one of `docker-compose.yml`).
+//! Tests of [`TestApp::memory`] and [`TestApp::sqlite`] need no database server at all.
+//!
+
+mod bulk;
+mod export;
+mod interstitial;
+mod links;
+mod qr;
+mod storage;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use clap::Parser;
+use serde_json::Value;
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+use crate::{cli::Args, context::AppState};
+
+pub struct TestApp {
+    pub ctx: AppState,
+    router: Router,
+}
+
+impl TestApp {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_args(pool, &[])
+    }
+
+    /// App with additional command line arguments, e.g. `&["--code-strategy", "sequential"]`
+    pub fn with_args(pool: PgPool, args: &[&str]) -> Self {
+        let ctx = AppState::with_pool(parse_args(args), pool).expect("Failed to create app context");
+        Self::from_ctx(ctx)
+    }
+
+    /// App using the in-memory storage
+    pub async fn memory() -> Self {
+        let ctx = AppState::new(parse_args(&["--storage", "memory"]))
+            .await
+            .expect("Failed to create app context");
+        Self::from_ctx(ctx)
+    }
+
+    /// App using a fresh, migrated in-memory SQLite database
+    pub async fn sqlite() -> Self {
+        // every connection to `:memory:` opens a database of its own
+        let args = parse_args(&["--database-url", "sqlite::memory:", "--max-db-conn-pool-size", "1"]);
+        let ctx = AppState::new(args).await.expect("Failed to create app context");
+        ctx.backend.migrate().await.expect("Failed to migrate SQLite database");
+        Self::from_ctx(ctx)
+    }
+
+    fn from_ctx(ctx: AppState) -> Self {
+        let router = super::http_router(ctx.clone());
+        Self { ctx, router }
+    }
+
+    /// Creates an API key for the owner (and the owner, if needed)
+    pub async fn api_key(&self, owner: &str, admin: bool) -> String {
+        let owner = self
+            .ctx
+            .api_keys
+            .find_or_create_owner(owner, admin)
+            .await
+            .expect("Failed to create owner");
+        let (_, key) = self
+            .ctx
+            .api_keys
+            .create_api_key(owner.owner_id, "test")
+            .await
+            .expect("Failed to create API key");
+        key
+    }
+
+    pub async fn request(&self, req: Request<Body>) -> Response {
+        self.router.clone().oneshot(req).await.expect("Router is infallible")
+    }
+
+    pub async fn get(&self, uri: &str, api_key: Option<&str>) -> Response {
+        self.send(Method::GET, uri, api_key, None).await
+    }
+
+    pub async fn send(&self, method: Method, uri: &str, api_key: Option<&str>, json: Option<Value>) -> Response {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(api_key) = api_key {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {api_key}"));
+        }
+        let body = match json {
+            Some(json) => {
+                req = req.header(header::CONTENT_TYPE, "application/json");
+                Body::from(json.to_string())
+            }
+            None => Body::empty(),
+        };
+        self.request(req.body(body).unwrap()).await
+    }
+
+    /// Creates a link through the API and returns it
+    pub async fn create_link(&self, api_key: &str, payload: Value) -> Value {
+        let res = self
+            .send(Method::POST, "/api/links", Some(api_key), Some(payload))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        body_json(res).await
+    }
+
+    /// Waits until all queued visits are written to the database
+    pub async fn flush_visits(&self) {
+        self.ctx.visit_queue.flush().await;
+    }
+}
+
+fn parse_args(args: &[&str]) -> Args {
+    Args::parse_from(["rust-axum-demo"].iter().chain(args).chain(&["serve"]))
+}
+
+pub async fn body_json(res: Response) -> Value {
+    let bytes = hyper::body::to_bytes(res.into_body())
+        .await
+        .expect("Failed to read body");
+    serde_json::from_slice(&bytes).expect("Body is not JSON")
+}
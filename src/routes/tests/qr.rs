@@ -0,0 +1,82 @@
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::Response,
+};
+use serde_json::json;
+
+use super::{body_json, TestApp};
+
+async fn get_qr(app: &TestApp, uri: &str, if_none_match: Option<&str>) -> Response {
+    let mut req = Request::builder().uri(uri).header(header::HOST, "sho.rt");
+    if let Some(etag) = if_none_match {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    app.request(req.body(Body::empty()).unwrap()).await
+}
+
+#[tokio::test]
+async fn qr_code_is_png_and_revalidated_by_etag() {
+    let app = TestApp::memory().await;
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "print" }))
+        .await;
+
+    let res = get_qr(&app, "/api/links/print/qr?size=300&margin=2", None).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "image/png");
+    let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let png = png::Decoder::new(body.as_ref()).read_info().unwrap();
+    let (width, height) = png.info().size();
+    // version 2 (25 modules) plus the margin, scaled to fit
+    assert_eq!((width, height), (290, 290));
+
+    let res = get_qr(&app, "/api/links/print/qr?size=300&margin=2", Some(&etag)).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::ETAG], etag.as_str());
+
+    // other options render another image
+    let res = get_qr(&app, "/api/links/print/qr?size=300", Some(&etag)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn qr_code_as_svg_with_colors() {
+    let app = TestApp::memory().await;
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "print" }))
+        .await;
+
+    let res = get_qr(
+        &app,
+        "/api/links/print/qr?format=svg&fg=%23ff0000&bg=ffffff00&ecc=h",
+        None,
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "image/svg+xml");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let svg = std::str::from_utf8(&body).unwrap();
+    assert!(svg.contains(r##"fill="#ff0000" fill-opacity="1.000""##));
+    assert!(svg.contains(r##"fill="#ffffff" fill-opacity="0.000""##));
+}
+
+#[tokio::test]
+async fn qr_code_rejects_invalid_options() {
+    let app = TestApp::memory().await;
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "print" }))
+        .await;
+
+    for (query, field) in [("size=10000", "size"), ("margin=100", "margin"), ("fg=ffffff", "fg")] {
+        let res = get_qr(&app, &format!("/api/links/print/qr?{query}"), None).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{query}");
+        assert_eq!(body_json(res).await["field"], field);
+    }
+
+    let res = get_qr(&app, "/api/links/unknown/qr", None).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,413 @@
+//!
+//! Scenarios run against all storages (Postgres, SQLite and in memory), to make sure they behave the same
+//!
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use super::{body_json, parse_args, TestApp};
+use crate::{
+    context::AppState,
+    db::{
+        links::{LinkChanges, NewLink},
+        owners::OwnerScope,
+    },
+};
+
+/// Declares a test per storage for a scenario
+macro_rules! storage_tests {
+    ($($scenario:ident),* $(,)?) => {
+        mod postgres {
+            use super::*;
+            $(
+                #[sqlx::test]
+                async fn $scenario(pool: PgPool) {
+                    super::$scenario(TestApp::new(pool)).await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            use super::*;
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    super::$scenario(TestApp::sqlite().await).await;
+                }
+            )*
+        }
+
+        mod memory {
+            use super::*;
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    super::$scenario(TestApp::memory().await).await;
+                }
+            )*
+        }
+    };
+}
+
+storage_tests!(
+    create_follow_and_count,
+    taken_code_conflicts,
+    list_is_paginated_and_scoped,
+    update_and_delete,
+    stats_are_bucketed,
+    export_is_scoped,
+    codes_are_unique_per_domain,
+    visits_of_deleted_links_are_skipped,
+    transactional_bulk_is_all_or_nothing,
+);
+
+///
+/// Writes of CLI commands (like `api-key create`) have to be committed, before the process exits: Another connection
+/// to the SQLite file has to see each of them, while the writing one is still open.
+///
+#[tokio::test]
+async fn sqlite_writes_are_committed_right_away() {
+    let path = std::env::temp_dir().join(format!("rust-axum-demo-{}.db", std::process::id()));
+    let remove = || {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    };
+    remove();
+    let url = format!("sqlite://{}", path.display());
+    // sequential codes, to reserve the link id up front
+    let connect = || AppState::new(parse_args(&["--database-url", &url, "--code-strategy", "sequential"]));
+    let writer = connect().await.unwrap();
+    writer.backend.migrate().await.unwrap();
+    let reader = connect().await.unwrap();
+
+    let owner = writer.api_keys.find_or_create_owner("alice", false).await.unwrap();
+    assert!(reader.api_keys.find_owner(owner.owner_id).await.unwrap().is_some());
+
+    let (api_key, key) = writer.api_keys.create_api_key(owner.owner_id, "cli").await.unwrap();
+    let found = reader.api_keys.find_active_api_key(&key).await.unwrap();
+    assert_eq!(found.map(|found| found.api_key_id), Some(api_key.api_key_id));
+
+    let new_link = NewLink {
+        url: "https://example.com/".to_string(),
+        code: None,
+        expires_at: None,
+        max_visits: None,
+        redirect_type: None,
+        owner_id: owner.owner_id,
+        domain_id: None,
+        password_hash: None,
+        title: None,
+        preview: false,
+    };
+    let link = writer.links.create(&writer.code_generator, &new_link).await.unwrap();
+    let found = reader
+        .links
+        .find_by_code(OwnerScope::All, None, &link.code)
+        .await
+        .unwrap();
+    assert_eq!(found.map(|found| found.link_id), Some(link.link_id));
+
+    let changes = LinkChanges {
+        url: Some("https://example.org/".to_string()),
+        ..Default::default()
+    };
+    writer
+        .links
+        .update(OwnerScope::All, link.link_id, &changes)
+        .await
+        .unwrap();
+    let found = reader
+        .links
+        .find_by_code(OwnerScope::All, None, &link.code)
+        .await
+        .unwrap();
+    assert_eq!(found.unwrap().url, "https://example.org/");
+
+    drop((writer, reader));
+    remove();
+}
+
+async fn create_follow_and_count(app: TestApp) {
+    let key = app.api_key("alice", false).await;
+    let link = app.create_link(&key, json!({ "url": "https://example.com" })).await;
+    let code = link["code"].as_str().unwrap();
+
+    let res = app.get(&format!("/api/links/{code}"), None).await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/");
+    app.get(&format!("/api/links/{code}"), None).await;
+    app.flush_visits().await;
+
+    let meta = body_json(app.get(&format!("/api/links/{code}/meta"), Some(&key)).await).await;
+    assert_eq!(meta["visits"], 2);
+
+    // the queue keeps working after a flush
+    app.get(&format!("/api/links/{code}"), None).await;
+    app.flush_visits().await;
+    let meta = body_json(app.get(&format!("/api/links/{code}/meta"), Some(&key)).await).await;
+    assert_eq!(meta["visits"], 3);
+}
+
+async fn taken_code_conflicts(app: TestApp) {
+    let key = app.api_key("alice", false).await;
+    let payload = json!({ "url": "https://example.com", "code": "taken" });
+    app.create_link(&key, payload.clone()).await;
+
+    let res = app.send(Method::POST, "/api/links", Some(&key), Some(payload)).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+async fn list_is_paginated_and_scoped(app: TestApp) {
+    let alice = app.api_key("alice", false).await;
+    let bob = app.api_key("bob", false).await;
+    for code in ["a1", "a2", "a3"] {
+        app.create_link(&alice, json!({ "url": "https://example.com", "code": code }))
+            .await;
+    }
+    app.create_link(&bob, json!({ "url": "https://example.com", "code": "b1" }))
+        .await;
+
+    let page = body_json(app.get("/api/links?limit=2", Some(&alice)).await).await;
+    assert_eq!(codes(&page), ["a1", "a2"]);
+    let cursor = page["next_cursor"].as_i64().unwrap();
+
+    let page = body_json(
+        app.get(&format!("/api/links?limit=2&cursor={cursor}"), Some(&alice))
+            .await,
+    )
+    .await;
+    assert_eq!(codes(&page), ["a3"]);
+    assert_eq!(page["next_cursor"], Value::Null);
+}
+
+async fn update_and_delete(app: TestApp) {
+    let alice = app.api_key("alice", false).await;
+    let bob = app.api_key("bob", false).await;
+    app.create_link(&alice, json!({ "url": "https://example.com", "code": "mine" }))
+        .await;
+
+    let change = json!({ "url": "https://example.org", "redirect_type": 308 });
+    let res = app
+        .send(Method::PATCH, "/api/links/mine", Some(&bob), Some(change.clone()))
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let link = body_json(
+        app.send(Method::PATCH, "/api/links/mine", Some(&alice), Some(change))
+            .await,
+    )
+    .await;
+    assert_eq!(link["url"], "https://example.org/");
+    assert_eq!(link["redirect_type"], 308);
+
+    let res = app.send(Method::DELETE, "/api/links/mine", Some(&bob), None).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = app.send(Method::DELETE, "/api/links/mine", Some(&alice), None).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(app.get("/api/links/mine", None).await.status(), StatusCode::NOT_FOUND);
+}
+
+async fn stats_are_bucketed(app: TestApp) {
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com", "code": "stats" }))
+        .await;
+
+    for referrer in [
+        "https://News.example.com/a",
+        "https://news.example.com/b",
+        "https://blog.example.com/",
+    ] {
+        let req = axum::http::Request::builder()
+            .uri("/api/links/stats")
+            .header(header::REFERER, referrer)
+            .header(header::ACCEPT_LANGUAGE, "de-AT,de;q=0.9")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.request(req).await;
+    }
+    app.get("/api/links/stats", None).await;
+    app.flush_visits().await;
+
+    let now = chrono::Utc::now();
+    let from = (now - chrono::Duration::hours(3)).format("%Y-%m-%dT%H:%M:%SZ");
+    let to = (now + chrono::Duration::minutes(1)).format("%Y-%m-%dT%H:%M:%SZ");
+    let res = app
+        .get(
+            &format!("/api/links/stats/stats?bucket=hour&from={from}&to={to}"),
+            Some(&key),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let stats = body_json(res).await;
+
+    assert_eq!(stats["total"], 4);
+    let timeline = stats["timeline"].as_array().unwrap();
+    assert!(timeline.len() == 4 || timeline.len() == 5, "{timeline:?}");
+    assert_eq!(timeline.iter().filter(|bucket| bucket["visits"] != 0).count(), 1);
+    assert_eq!(
+        stats["referrers"],
+        json!([
+            { "key": "news.example.com", "visits": 2 },
+            { "key": "blog.example.com", "visits": 1 },
+            { "key": null, "visits": 1 },
+        ])
+    );
+    // the language tells nothing about where the visitor is
+    assert_eq!(stats["countries"], json!([{ "key": null, "visits": 4 }]));
+}
+
+async fn export_is_scoped(app: TestApp) {
+    let alice = app.api_key("alice", false).await;
+    let bob = app.api_key("bob", false).await;
+    app.create_link(&alice, json!({ "url": "https://example.com/a", "code": "a" }))
+        .await;
+    app.create_link(&bob, json!({ "url": "https://example.com/b", "code": "b" }))
+        .await;
+    app.get("/api/links/a", None).await;
+    app.get("/api/links/b", None).await;
+    app.flush_visits().await;
+
+    let res = app.get("/api/links/export?format=jsonl", Some(&alice)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+    let links = body_lines(res).await;
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0]["code"], "a");
+    assert_eq!(links[0]["visits"], 1);
+
+    let visits = body_lines(
+        app.get("/api/links/export?format=jsonl&kind=visits", Some(&alice))
+            .await,
+    )
+    .await;
+    assert_eq!(visits.len(), 1);
+    assert_eq!(visits[0]["code"], "a");
+}
+
+async fn codes_are_unique_per_domain(app: TestApp) {
+    app.ctx
+        .domains
+        .upsert_domain("go.example.org", Some("https://example.org/"))
+        .await
+        .unwrap();
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com/default", "code": "same" }))
+        .await;
+    let link = app
+        .create_link(
+            &key,
+            json!({ "url": "https://example.com/custom", "code": "same", "domain": "Go.Example.org" }),
+        )
+        .await;
+    assert_eq!(link["short_url"], "http://go.example.org/same");
+
+    let follow = |host: &'static str, code: &'static str| {
+        let req = Request::builder()
+            .uri(format!("/{code}"))
+            .header(header::HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        app.request(req)
+    };
+    let res = follow("go.example.org:8080", "same").await;
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/custom");
+    let res = follow("sho.rt", "same").await;
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/default");
+    // unknown codes of the domain go to its fallback
+    let res = follow("go.example.org", "unknown").await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "https://example.org/");
+    assert_eq!(follow("sho.rt", "unknown").await.status(), StatusCode::NOT_FOUND);
+
+    let meta = body_json(app.get("/api/links/same/meta?domain=go.example.org", Some(&key)).await).await;
+    assert_eq!(meta["url"], "https://example.com/custom");
+    assert_eq!(meta["domain"], "go.example.org");
+    let meta = body_json(app.get("/api/links/same/meta", Some(&key)).await).await;
+    assert_eq!(meta["url"], "https://example.com/default");
+    assert_eq!(meta["domain"], Value::Null);
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/links",
+            Some(&key),
+            Some(json!({ "url": "https://example.com", "domain": "unknown.example.org" })),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body_json(res).await["field"], "domain");
+}
+
+async fn visits_of_deleted_links_are_skipped(app: TestApp) {
+    let key = app.api_key("alice", false).await;
+    app.create_link(&key, json!({ "url": "https://example.com/a", "code": "gone" }))
+        .await;
+    app.create_link(&key, json!({ "url": "https://example.com/b", "code": "kept" }))
+        .await;
+
+    app.get("/api/links/gone", None).await;
+    app.get("/api/links/kept", None).await;
+    // while the visits are still queued
+    let res = app.send(Method::DELETE, "/api/links/gone", Some(&key), None).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    app.flush_visits().await;
+
+    let meta = body_json(app.get("/api/links/kept/meta", Some(&key)).await).await;
+    assert_eq!(meta["visits"], 1);
+}
+async fn transactional_bulk_is_all_or_nothing(app: TestApp) {
+    let key = app.api_key("alice", false).await;
+    let bulk = |payload: Value| {
+        app.send(
+            Method::POST,
+            "/api/links/bulk?mode=transactional",
+            Some(&key),
+            Some(payload),
+        )
+    };
+
+    // valid on their own, but the second one with the same code fails within the transaction
+    let payload = json!([
+        { "url": "https://example.com/a", "code": "first" },
+        { "url": "https://example.com/b" },
+        { "url": "https://example.com/c", "code": "first" },
+    ]);
+    let body = body_json(bulk(payload).await).await;
+    assert_eq!(body["created"], 0);
+    assert_eq!(body["results"][0]["status"], "skipped");
+    assert_eq!(body["results"][2]["error"], "conflict");
+    assert_eq!(app.get("/api/links/first", None).await.status(), StatusCode::NOT_FOUND);
+    let page = body_json(app.get("/api/links", Some(&key)).await).await;
+    assert!(codes(&page).is_empty());
+
+    let payload = json!([{ "url": "https://example.com/a", "code": "first" }, { "url": "https://example.com/b" }]);
+    let body = body_json(bulk(payload).await).await;
+    assert_eq!(body["created"], 2);
+    let code = body["results"][1]["link"]["code"].as_str().unwrap();
+    assert_eq!(
+        app.get(&format!("/api/links/{code}"), None).await.status(),
+        StatusCode::TEMPORARY_REDIRECT
+    );
+}
+
+async fn body_lines(res: axum::response::Response) -> Vec<Value> {
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn codes(page: &Value) -> Vec<&str> {
+    page["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|link| link["code"].as_str().unwrap())
+        .collect()
+}
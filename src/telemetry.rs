@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::OnceLock};
 
 use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use opentelemetry::sdk::trace::RandomIdGenerator;
@@ -98,6 +98,8 @@ pub(crate) async fn setup_tracing(args: &Args) {
     Registry::default()
         .with(
             tracing_subscriber::fmt::layer()
+                // keep stdout free for the output of CLI commands
+                .with_writer(std::io::stderr)
                 .with_ansi(true)
                 .with_timer(tracing_subscriber::fmt::time::time())
                 .with_filter(stdout_log_filter),
@@ -107,7 +109,14 @@ pub(crate) async fn setup_tracing(args: &Args) {
         .init();
 }
 
+/// The recorder is global, so it is only installed once (tests create several app contexts)
+static PROM_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
 pub fn setup_metrics() -> PrometheusHandle {
+    PROM_HANDLE.get_or_init(install_metrics_recorder).clone()
+}
+
+fn install_metrics_recorder() -> PrometheusHandle {
     // cf. https://github.com/tokio-rs/axum/blob/main/examples/prometheus-metrics/src/main.rs
     const EXPONENTIAL_SECONDS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
 
@@ -122,6 +131,14 @@ pub fn setup_metrics() -> PrometheusHandle {
 
     metrics::register_counter!("links_visited");
     metrics::register_counter!("links_created");
+    metrics::register_counter!("links_updated");
+    metrics::register_counter!("links_deleted");
+    metrics::register_counter!("links_expired_visited");
+    metrics::register_counter!("rate_limit_rejections");
+    metrics::register_counter!("visits_dropped");
+    metrics::register_gauge!("visit_queue_depth");
+    metrics::register_counter!("link_cache_hits");
+    metrics::register_counter!("link_cache_misses");
 
     handler
 }
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::cli::Args;
+
+///
+/// HTML pages served to visitors instead of redirecting them right away.
+/// Each can be replaced by a file of the same name in `--templates-dir`, e.g. to brand them.
+///
+/// Templates contain placeholders like `{{url}}`, which are replaced by the HTML escaped value.
+/// Unknown placeholders are left as they are.
+///
+pub struct Templates {
+    /// asks for the password of a protected link, placeholders: `{{error}}` (empty on the first attempt)
+    password: String,
+    /// shows where a link leads, placeholders: `{{title}}`, `{{url}}`, `{{host}}` and `{{code}}`
+    preview: String,
+}
+
+impl Templates {
+    pub fn from_args(args: &Args) -> anyhow::Result<Self> {
+        let dir = args.templates_dir.as_deref();
+        Ok(Self {
+            password: load(dir, "password.html", include_str!("../templates/password.html"))?,
+            preview: load(dir, "preview.html", include_str!("../templates/preview.html"))?,
+        })
+    }
+
+    pub fn password(&self, error: &str) -> String {
+        render(&self.password, &[("error", error)])
+    }
+
+    pub fn preview(&self, title: &str, url: &str, host: &str, code: &str) -> String {
+        render(
+            &self.preview,
+            &[("title", title), ("url", url), ("host", host), ("code", code)],
+        )
+    }
+}
+
+/// The template of the directory, the built-in one if the directory does not have it
+fn load(dir: Option<&Path>, name: &str, builtin: &str) -> anyhow::Result<String> {
+    let Some(path) = dir.map(|dir| dir.join(name)).filter(|path| path.exists()) else {
+        return Ok(builtin.to_string());
+    };
+    std::fs::read_to_string(&path).with_context(|| format!("Failed to read template {}", path.display()))
+}
+
+/// In a single pass, so placeholders within the values are not replaced in turn
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut page = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        page.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find("}}").and_then(|end| {
+            let name = rest[2..end].trim();
+            let (_, value) = values.iter().find(|(known, _)| *known == name)?;
+            Some((end + 2, value))
+        });
+        match value {
+            Some((len, value)) => {
+                page.push_str(&escape_html(value));
+                rest = &rest[len..];
+            }
+            None => {
+                page.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    page.push_str(rest);
+    page
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::{
+    context::AppState,
+    db::{domains::Domains, links::NewLink},
+    password::{hash_password, MAX_PASSWORD_LENGTH},
+    redirect::RedirectType,
+};
+
+/// Longer titles would not fit the preview page anyway
+const MAX_TITLE_LENGTH: usize = 200;
+
+#[derive(Debug, thiserror::Error)]
+pub enum UrlError {
+    #[error("not a valid absolute URL ({0})")]
+    Invalid(#[from] url::ParseError),
+    #[error("scheme '{0}' is not allowed")]
+    SchemeNotAllowed(String),
+    #[error("URL has no host")]
+    MissingHost,
+}
+
+///
+/// Parses and normalizes a link target.
+///
+/// Only absolute URLs with one of the `allowed_schemes` are accepted. Parsing already takes care of
+/// lowercasing the host, converting internationalized domain names to punycode and dropping default ports,
+/// so the normalized form is just the serialized URL.
+///
+pub fn normalize_url(raw: &str, allowed_schemes: &[String]) -> Result<String, UrlError> {
+    let url = Url::parse(raw.trim())?;
+
+    if !allowed_schemes
+        .iter()
+        .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+    {
+        return Err(UrlError::SchemeNotAllowed(url.scheme().to_string()));
+    }
+    if url.host().is_none() {
+        return Err(UrlError::MissingHost);
+    }
+
+    Ok(url.into())
+}
+
+///
+/// Normalizes a host name (e.g. of a domain or the `Host` header) like the host of a URL, dropping the port.
+/// Returns `None`, if it is not a plain host name.
+///
+pub fn normalize_host(raw: &str) -> Option<String> {
+    let url = Url::parse(&format!("http://{}", raw.trim())).ok()?;
+    if url.path() != "/" || url.query().is_some() || !url.username().is_empty() {
+        return None;
+    }
+    url.host_str().map(str::to_string)
+}
+
+/// A request field (or import column), which did not pass validation
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid {field}: {msg}")]
+pub struct InvalidField {
+    pub field: &'static str,
+    pub msg: String,
+}
+
+impl InvalidField {
+    pub fn new(field: &'static str, msg: impl ToString) -> Self {
+        Self {
+            field,
+            msg: msg.to_string(),
+        }
+    }
+}
+
+/// A link as submitted through the API or an import file, before it is validated
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateLinkPayload {
+    pub url: String,
+    pub code: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_visits: Option<i32>,
+    pub redirect_type: Option<RedirectType>,
+    /// host of a registered domain, the link lives on the default domain if not given
+    pub domain: Option<String>,
+    /// visitors have to enter it before being redirected, only its hash is stored
+    pub password: Option<String>,
+    /// shown on the preview page
+    pub title: Option<String>,
+    /// show visitors a preview page with the target, instead of redirecting them right away
+    pub preview: Option<bool>,
+}
+
+impl CreateLinkPayload {
+    ///
+    /// Checks the payload against the server config and the registered `domains` and turns it into a link of
+    /// the owner. The password (if any) is hashed on a blocking thread.
+    ///
+    pub async fn validate(self, ctx: &AppState, owner_id: i32, domains: &Domains) -> Result<NewLink, InvalidField> {
+        let url =
+            normalize_url(&self.url, &ctx.args.allowed_url_schemes).map_err(|err| InvalidField::new("url", err))?;
+
+        if let Some(ref code) = self.code {
+            ctx.code_generator
+                .validate(code)
+                .map_err(|err| InvalidField::new("code", err))?;
+        }
+
+        if self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(InvalidField::new("expires_at", "must be in the future"));
+        }
+        if self.max_visits.is_some_and(|max_visits| max_visits < 1) {
+            return Err(InvalidField::new("max_visits", "must be at least 1"));
+        }
+
+        let domain_id = match self.domain {
+            Some(ref domain) => {
+                let domain = normalize_host(domain)
+                    .and_then(|host| domains.by_host(&host))
+                    .ok_or_else(|| InvalidField::new("domain", "not a registered domain"))?;
+                Some(domain.domain_id)
+            }
+            None => None,
+        };
+
+        if self
+            .title
+            .as_ref()
+            .is_some_and(|title| title.chars().count() > MAX_TITLE_LENGTH)
+        {
+            return Err(InvalidField::new(
+                "title",
+                format!("must have at most {MAX_TITLE_LENGTH} characters"),
+            ));
+        }
+
+        let password_hash = match self.password {
+            Some(ref password) if password.is_empty() || password.chars().count() > MAX_PASSWORD_LENGTH => {
+                return Err(InvalidField::new(
+                    "password",
+                    format!("must have 1 to {MAX_PASSWORD_LENGTH} characters"),
+                ));
+            }
+            Some(password) => {
+                // argon2 takes a while on purpose, which must not block other requests
+                let hash = tokio::task::spawn_blocking(move || hash_password(&password))
+                    .await
+                    .unwrap_or_else(|err| Err(err.into()))
+                    .map_err(|err| InvalidField::new("password", err))?;
+                Some(hash)
+            }
+            None => None,
+        };
+
+        Ok(NewLink {
+            url,
+            code: self.code,
+            expires_at: self.expires_at,
+            max_visits: self.max_visits,
+            redirect_type: self.redirect_type,
+            owner_id,
+            domain_id,
+            password_hash,
+            title: self.title.filter(|title| !title.trim().is_empty()),
+            preview: self.preview.unwrap_or(false),
+        })
+    }
+}
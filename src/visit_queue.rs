@@ -0,0 +1,177 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    cli::Args,
+    db::{link_visit::NewVisit, store::VisitStore},
+};
+
+///
+/// Bounded queue of visits, which are written to the database in batches by a background worker.
+/// This keeps the database round trip out of the redirect. When the queue is full, visits are dropped
+/// (and counted in the `visits_dropped` metric) instead of slowing down the redirects.
+///
+pub struct VisitQueue {
+    sender: mpsc::Sender<Message>,
+    /// signal to stop the worker and the worker itself, taken on shutdown
+    worker: Mutex<Option<(oneshot::Sender<()>, JoinHandle<()>)>>,
+}
+
+impl VisitQueue {
+    /// Creates the queue and spawns its worker
+    pub fn start(store: Arc<dyn VisitStore>, args: &Args) -> Self {
+        let (sender, receiver) = mpsc::channel(args.visit_queue_size);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let worker = tokio::spawn(run_worker(
+            store,
+            receiver,
+            sender.downgrade(),
+            stop_rx,
+            args.visit_batch_size,
+            args.visit_flush_interval.into(),
+        ));
+
+        Self {
+            sender,
+            worker: Mutex::new(Some((stop_tx, worker))),
+        }
+    }
+
+    /// Queues a visit without waiting, drops it if the queue is full
+    pub fn push(&self, link_id: i32, visit: NewVisit) {
+        match self.sender.try_send(Message::Visit(link_id, visit)) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                debug!(link_id, "Visit queue is full, dropping visit");
+                metrics::increment_counter!("visits_dropped", "reason" => "queue_full");
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                debug!(link_id, "Visit queue is closed, dropping visit");
+                metrics::increment_counter!("visits_dropped", "reason" => "shutdown");
+            }
+        }
+        record_depth(&self.sender);
+    }
+
+    ///
+    /// Waits until all visits queued so far are written, without waiting for a full batch or the flush interval.
+    /// Unlike [`Self::shutdown`], the queue keeps accepting visits.
+    ///
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.sender.send(Message::Flush(done_tx)).await.is_ok() {
+            // an error means the worker stopped, which wrote all visits on its way out
+            done_rx.await.ok();
+        }
+    }
+
+    ///
+    /// Stops accepting visits and waits until all queued ones are written.
+    /// Call this once no more requests are served, i.e. after the graceful shutdown of the server.
+    ///
+    pub async fn shutdown(&self) {
+        let Some((stop_tx, worker)) = self.worker.lock().expect("visit queue lock poisoned").take() else {
+            return;
+        };
+        info!(queued = depth(&self.sender), "Flush visit queue");
+        stop_tx.send(()).ok();
+        if let Err(err) = worker.await {
+            warn!(err = ?err, "Visit queue worker failed!");
+        }
+    }
+}
+
+/// What the worker is sent through the queue
+// boxing the visits is not worth it, the other variant is only ever sent by tests
+#[cfg_attr(test, allow(clippy::large_enum_variant))]
+enum Message {
+    Visit(i32, NewVisit),
+    /// asks the worker to write the visits queued before right away, it answers once they are written
+    #[cfg(test)]
+    Flush(oneshot::Sender<()>),
+}
+
+async fn run_worker(
+    store: Arc<dyn VisitStore>,
+    mut receiver: mpsc::Receiver<Message>,
+    // weak, so the worker does not keep the queue open
+    sender: mpsc::WeakSender<Message>,
+    mut stop: oneshot::Receiver<()>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut interval = tokio::time::interval(flush_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(message) => handle(store.as_ref(), &mut batch, batch_size, message).await,
+                None => break,
+            },
+            _ = interval.tick() => flush(store.as_ref(), &mut batch).await,
+            _ = &mut stop => break,
+        }
+        if let Some(sender) = sender.upgrade() {
+            record_depth(&sender);
+        }
+    }
+
+    // drain whatever is still queued, senders are rejected from now on
+    receiver.close();
+    while let Some(message) = receiver.recv().await {
+        handle(store.as_ref(), &mut batch, batch_size, message).await;
+    }
+    flush(store.as_ref(), &mut batch).await;
+    metrics::gauge!("visit_queue_depth", 0.0);
+}
+
+async fn handle(store: &dyn VisitStore, batch: &mut Vec<(i32, NewVisit)>, batch_size: usize, message: Message) {
+    match message {
+        Message::Visit(link_id, visit) => {
+            batch.push((link_id, visit));
+            if batch.len() >= batch_size {
+                flush(store, batch).await;
+            }
+        }
+        #[cfg(test)]
+        Message::Flush(done) => {
+            flush(store, batch).await;
+            done.send(()).ok();
+        }
+    }
+}
+
+/// Number of visits waiting to be written
+fn depth<T>(sender: &mpsc::Sender<T>) -> usize {
+    sender.max_capacity() - sender.capacity()
+}
+
+fn record_depth<T>(sender: &mpsc::Sender<T>) {
+    metrics::gauge!("visit_queue_depth", depth(sender) as f64);
+}
+
+async fn flush(store: &dyn VisitStore, batch: &mut Vec<(i32, NewVisit)>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    match store.insert_batch(batch).await {
+        Ok(inserted) => debug!(inserted, "Visits written"),
+        Err(err) => {
+            warn!(err = ?err, visits = batch.len(), "Failed to write visits, dropping them!");
+            metrics::counter!("visits_dropped", batch.len() as u64, "reason" => "db_error");
+        }
+    }
+    batch.clear();
+}
@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap};
+use chrono::Utc;
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use woothee::parser::Parser;
+
+use crate::{cli::Args, db::link_visit::NewVisit};
+
+/// How much of the visitor's IP address is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IpMode {
+    /// The complete address
+    Full,
+    /// Only the network part (/24 for IPv4, /48 for IPv6)
+    Truncate,
+    /// A salted hash, which allows counting unique visitors without knowing the address
+    Hash,
+    /// Nothing at all
+    Omit,
+}
+
+///
+/// Collects the details of a visit from the request of the visitor
+///
+pub fn visit_from_request(headers: &HeaderMap, ip: Option<IpAddr>, args: &Args) -> NewVisit {
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(ToString::to_string)
+    };
+
+    let user_agent = header_value(header::USER_AGENT.as_str());
+    let accept_language = header_value(header::ACCEPT_LANGUAGE.as_str());
+    let ua_info = user_agent.as_deref().map(classify_user_agent).unwrap_or_default();
+
+    // only a proxy or CDN (e.g. `CF-IPCountry`) knows the country, the region of the language is just a preference
+    let country = args
+        .country_header
+        .as_deref()
+        .and_then(header_value)
+        .map(|country| country.to_uppercase());
+
+    NewVisit {
+        ts: Utc::now(),
+        referrer: header_value(header::REFERER.as_str()),
+        ip: ip.and_then(|ip| anonymize_ip(ip, args.visitor_ip_mode, args.ip_hash_salt.as_deref())),
+        user_agent,
+        accept_language,
+        country,
+        browser: ua_info.browser,
+        os: ua_info.os,
+        device: ua_info.device,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserAgentInfo {
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    /// one of `desktop`, `mobile`, `bot` or `other`
+    pub device: Option<String>,
+}
+
+pub fn classify_user_agent(user_agent: &str) -> UserAgentInfo {
+    let Some(result) = Parser::new().parse(user_agent) else {
+        return UserAgentInfo::default();
+    };
+    let known = |value: &str| (value != woothee::woothee::VALUE_UNKNOWN).then(|| value.to_string());
+
+    let device = match result.category {
+        "pc" => "desktop",
+        "smartphone" | "mobilephone" => "mobile",
+        "crawler" => "bot",
+        _ => "other",
+    };
+
+    UserAgentInfo {
+        browser: known(result.name),
+        os: known(result.os),
+        device: Some(device.to_string()),
+    }
+}
+
+pub fn anonymize_ip(ip: IpAddr, mode: IpMode, salt: Option<&str>) -> Option<String> {
+    match mode {
+        IpMode::Full => Some(ip.to_string()),
+        IpMode::Truncate => Some(match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                IpAddr::from([a, b, c, 0]).to_string()
+            }
+            IpAddr::V6(ip) => {
+                let segments = ip.segments();
+                IpAddr::from([segments[0], segments[1], segments[2], 0, 0, 0, 0, 0]).to_string()
+            }
+        }),
+        IpMode::Hash => {
+            let mut hasher = Sha256::new();
+            hasher.update(salt.unwrap_or_default().as_bytes());
+            hasher.update(ip.to_string().as_bytes());
+            Some(hex::encode(hasher.finalize()))
+        }
+        IpMode::Omit => None,
+    }
+}